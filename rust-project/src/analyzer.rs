@@ -1,18 +1,131 @@
-use crate::interp::{extract_insert_keys, get_interpdata, get_simple_insertkey};
+use crate::interp::{
+    extract_insert_keys, get_interpdata, get_simple_insertkey, recursive_unescape, value_to_string, BUILTIN_INSERT_KEYS,
+};
 use crate::model::{Program, ProgramLoadContext, Task};
+use crate::runtime::COERCE_TYPES;
 use anyhow::{anyhow, Result};
 use serde_json::{Map, Value};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
 
+const TYPE_NAMES: &[&str] = &["string", "number", "boolean", "array", "object", "null"];
+
+/// Every recognized task `cmd` with its required and optional fields. The analyzer
+/// checks required fields against this table and `--list-cmds` prints it.
+pub static TASK_SPECS: &[(&str, &[&str], &[&str])] = &[
+    ("print", &["text"], &[]),
+    ("clear", &[], &[]),
+    ("break", &[], &[]),
+    ("continue", &[], &[]),
+    ("sleep", &["seconds"], &[]),
+    ("set", &["item", "output_name"], &[]),
+    ("unescape", &["item", "output_name"], &[]),
+    ("read", &["path", "output_name"], &["format"]),
+    ("http_get", &["url", "output_name"], &["headers", "timeout_seconds", "status_name", "allow_error_status", "parse_json"]),
+    ("write", &["item", "path"], &[]),
+    ("show_inserts", &[], &["pattern", "keys"]),
+    ("random_int", &["min", "max", "output_name"], &["seed"]),
+    ("random_float", &["output_name"], &["min", "max"]),
+    ("random_choice", &["list", "output_name"], &[]),
+    ("list_join", &["list", "before", "between", "after", "output_name"], &[]),
+    ("list_concat", &["lists", "output_name"], &[]),
+    ("zip_lists", &["lists", "output_name"], &["strict"]),
+    ("list_append", &["list", "item", "output_name"], &[]),
+    ("list_remove", &["list", "item", "output_name"], &[]),
+    ("list_index", &["list", "index", "output_name"], &[]),
+    ("list_slice", &["list", "from_index", "to_index", "output_name"], &[]),
+    ("list_sort", &["list", "output_name"], &["order"]),
+    ("list_reverse", &["list", "output_name"], &[]),
+    ("list_unique", &["list", "output_name"], &[]),
+    ("list_dedupe", &["list", "output_name"], &[]),
+    ("list_shuffle", &["list", "output_name"], &["seed"]),
+    ("list_find", &["list", "pattern", "output_name"], &["index_name"]),
+    ("list_contains", &["list", "item", "output_name"], &[]),
+    ("list_filter", &["list", "pattern", "output_name"], &["negate"]),
+    ("list_map", &["list", "wildcard_maps", "output_name"], &["repeat_until_done", "case_insensitive"]),
+    ("list_pop", &["list", "output_name"], &["item_name", "allow_empty"]),
+    ("list_push_front", &["list", "item", "output_name"], &[]),
+    ("string_trim", &["text", "output_name"], &["mode", "side", "chars"]),
+    ("string_upper", &["text", "output_name"], &[]),
+    ("string_lower", &["text", "output_name"], &[]),
+    ("string_pad", &["text", "width", "align", "output_name"], &["pad_char"]),
+    ("string_slice", &["text", "from_index", "to_index", "output_name"], &[]),
+    ("regex_match", &["text", "pattern", "output_name"], &["groups_name", "capture_groups", "multi_line", "dot_all"]),
+    ("regex_find_all", &["text", "pattern", "output_name"], &["capture_group", "multi_line", "dot_all"]),
+    ("json_parse", &["text", "output_name"], &["format", "on_error", "fallback"]),
+    ("json_stringify", &["item", "output_name"], &["pretty"]),
+    ("object_get", &["object", "path", "output_name"], &["default"]),
+    ("object_set", &["object", "path", "item", "output_name"], &[]),
+    ("merge_objects", &["objects", "output_name"], &["mode"]),
+    ("object_has_key", &["object", "key", "output_name"], &[]),
+    ("timestamp", &["output_name"], &["format", "utc"]),
+    ("uuid", &["output_name"], &["format"]),
+    ("base64_encode", &["text", "output_name"], &[]),
+    ("base64_decode", &["text", "output_name"], &["encoding"]),
+    ("hash", &["text", "output_name"], &["algorithm"]),
+    ("error", &["message"], &["code"]),
+    ("env_get", &["name", "output_name"], &["fallback"]),
+    ("assert", &["condition"], &["message", "level", "warn_only"]),
+    ("type_of", &["item", "output_name"], &["expected"]),
+    ("coerce", &["item", "to_type", "output_name"], &[]),
+    ("string_split", &["text", "separator", "output_name"], &["limit"]),
+    ("string_replace", &["text", "from", "to", "output_name"], &["count"]),
+    ("user_input", &["prompt", "output_name"], &["validate_pattern", "validate_error", "masked", "default"]),
+    ("user_choice", &["list", "description", "output_name"], &["multi_select"]),
+    ("user_confirm", &["question", "output_name"], &["default"]),
+    ("await_insert", &["name"], &[]),
+    ("label", &["name"], &[]),
+    ("goto", &["name"], &[]),
+    ("goto_map", &["text", "target_maps"], &["case_insensitive"]),
+    ("switch", &["value", "cases"], &["default"]),
+    ("replace_map", &["item", "output_name", "wildcard_maps"], &["repeat_until_done", "case_insensitive"]),
+    ("for", &["name_list_map", "tasks"], &[]),
+    ("if", &["condition", "then"], &["else"]),
+    ("repeat", &["count", "tasks"], &["counter_name"]),
+    ("while", &["condition", "tasks"], &["max_iterations"]),
+    ("try", &["tasks", "on_error"], &["error_name"]),
+    ("benchmark", &["task", "output_name"], &["label"]),
+    ("retry", &["task"], &["max_attempts", "delay_seconds"]),
+    ("timeout", &["task", "seconds"], &["on_timeout"]),
+    ("serial", &["tasks"], &[]),
+    ("parallel_wait", &["tasks"], &[]),
+    ("parallel_race", &["tasks"], &[]),
+    ("parallel_limit", &["tasks"], &["max_concurrent"]),
+    ("run_task", &["task_name"], &[]),
+    ("map", &["list", "task_name", "output_name"], &["item_name", "result_name", "on_error"]),
+    ("filter_list", &["list", "task_name", "output_name"], &["item_name", "result_name", "on_error"]),
+    ("reduce", &["list", "task_name", "initial", "output_name"], &["item_name", "accumulator_name"]),
+    ("delete", &["wildcards"], &[]),
+    ("delete_except", &["wildcards"], &[]),
+    ("math", &["input", "output_name"], &[]),
+    (
+        "chat",
+        &["messages", "output_name"],
+        &[
+            "model", "n_outputs", "start_str", "stop_str", "sections", "hide_start_str",
+            "hide_stop_str", "shown", "choices_list_name", "choices_list", "extra_body",
+            "max_completion_tokens", "temperature", "seed", "stop", "api_url", "api_key", "voice_path",
+            "voice_speaker", "tools", "usage_name", "json_output", "gbnf_grammar", "gbnf_file", "dry_run_response",
+        ],
+    ),
+    ("speak", &["text", "voice_path"], &["voice_speaker"]),
+];
+
+#[derive(Debug, PartialEq)]
+pub enum DiagLevel {
+    Error,
+    Warning,
+}
+
 #[derive(Debug)]
 pub struct Diagnostic {
     pub message: String,
     pub label: Option<String>,
     pub line: Option<i64>,
+    pub level: DiagLevel,
 }
 
-pub fn analyze_program(program: &Program, ctx: &ProgramLoadContext) -> Result<()> {
+pub fn analyze_program(program: &Program, ctx: &ProgramLoadContext) -> Result<Vec<Diagnostic>> {
     let mut diags = Vec::new();
 
     let default_inserts = program
@@ -30,6 +143,7 @@ pub fn analyze_program(program: &Program, ctx: &ProgramLoadContext) -> Result<()
     analyze_task_list(
         &program.order,
         "order",
+        &HashSet::new(),
         &named,
         &default_inserts,
         ctx,
@@ -40,6 +154,7 @@ pub fn analyze_program(program: &Program, ctx: &ProgramLoadContext) -> Result<()
         analyze_task_list(
             &[task.clone()],
             &format!("named_tasks.{name}"),
+            &HashSet::new(),
             &named,
             &default_inserts,
             ctx,
@@ -47,28 +162,38 @@ pub fn analyze_program(program: &Program, ctx: &ProgramLoadContext) -> Result<()
         );
     }
 
-    if diags.is_empty() {
-        Ok(())
+    warn_unreachable(program, &mut diags);
+    check_task_cycles(program, &mut diags);
+
+    let (errors, warnings): (Vec<_>, Vec<_>) = diags.into_iter().partition(|d| d.level == DiagLevel::Error);
+    if errors.is_empty() {
+        Ok(warnings)
     } else {
         let mut msg = String::from("Program validation failed:\n");
-        for d in diags {
-            let line = d.line.map(|l| format!("line {l}")).unwrap_or_default();
-            let label = d.label.unwrap_or_default();
-            msg.push_str(&format!(" - {line} {label} {}\n", d.message));
+        for d in errors {
+            msg.push_str(&format!(" - {}\n", format_diagnostic(&d)));
         }
         Err(anyhow!(msg))
     }
 }
 
+pub fn format_diagnostic(d: &Diagnostic) -> String {
+    let line = d.line.map(|l| format!("line {l}")).unwrap_or_default();
+    let label = d.label.clone().unwrap_or_default();
+    format!("{line} {label} {}", d.message)
+}
+
 fn analyze_task_list(
     tasks: &[Task],
     scope_name: &str,
+    outer_labels: &HashSet<String>,
     named_tasks: &HashSet<String>,
     default_inserts: &Map<String, Value>,
     ctx: &ProgramLoadContext,
     diags: &mut Vec<Diagnostic>,
 ) {
-    let labels = collect_labels_for_list(tasks, diags);
+    let mut labels = collect_labels_for_list(tasks, diags);
+    labels.extend(outer_labels.iter().cloned());
     for task in tasks {
         validate_task(
             task,
@@ -79,7 +204,8 @@ fn analyze_task_list(
             ctx,
             diags,
         );
-        if let Some(subtasks) = task.get("tasks").and_then(Value::as_array) {
+        let cmd = task.get("cmd").and_then(Value::as_str);
+        if let Some(subtasks) = task.get("tasks").and_then(Value::as_array).filter(|_| cmd != Some("try")) {
             let subtasks = subtasks
                 .iter()
                 .filter_map(|v| super_task(v).ok())
@@ -88,6 +214,53 @@ fn analyze_task_list(
                 analyze_task_list(
                     &subtasks,
                     scope_name,
+                    &HashSet::new(),
+                    named_tasks,
+                    default_inserts,
+                    ctx,
+                    diags,
+                );
+            }
+        }
+        // These commands pass gotos to unknown labels upward, so their nested tasks may target enclosing labels.
+        let inheriting_fields: &[&str] = match cmd {
+            Some("try") => &["tasks", "on_error"],
+            Some("if") => &["then", "else"],
+            Some("benchmark") | Some("retry") => &["task"],
+            Some("timeout") => &["task", "on_timeout"],
+            _ => &[],
+        };
+        for field in inheriting_fields {
+            let subtasks = match task.get(*field) {
+                Some(Value::Object(obj)) => vec![obj.clone()],
+                Some(Value::Array(arr)) => arr.iter().filter_map(|v| super_task(v).ok()).collect(),
+                _ => Vec::new(),
+            };
+            if !subtasks.is_empty() {
+                analyze_task_list(
+                    &subtasks,
+                    scope_name,
+                    &labels,
+                    named_tasks,
+                    default_inserts,
+                    ctx,
+                    diags,
+                );
+            }
+        }
+        // Each switch branch is checked on its own, like a single-task `then`.
+        if cmd == Some("switch") {
+            let cases = task.get("cases").and_then(Value::as_array).into_iter().flatten();
+            let branches = cases
+                .filter_map(Value::as_object)
+                .flat_map(|case| case.values())
+                .chain(task.get("default"))
+                .filter_map(Value::as_object);
+            for branch in branches {
+                analyze_task_list(
+                    std::slice::from_ref(branch),
+                    scope_name,
+                    &labels,
                     named_tasks,
                     default_inserts,
                     ctx,
@@ -115,41 +288,93 @@ fn validate_task(
         }
     };
 
+    if let Some((_, required, _)) = TASK_SPECS.iter().find(|(name, _, _)| *name == cmd) {
+        require_fields(task, required, diags);
+    }
+
     match cmd {
         "print" => {
-            require_fields(task, &["text"], diags);
             require_string(task, "text", default_inserts, ctx, diags);
         }
         "clear" => {}
+        "break" | "continue" => {}
         "sleep" => {
-            require_fields(task, &["seconds"], diags);
             require_number_or_string(task, "seconds", default_inserts, ctx, diags);
         }
         "set" => {
-            require_fields(task, &["item", "output_name"], diags);
             require_string(task, "output_name", default_inserts, ctx, diags);
+            warn_builtin_output_name(task, diags);
         }
         "unescape" => {
-            require_fields(task, &["item", "output_name"], diags);
             require_string(task, "output_name", default_inserts, ctx, diags);
         }
+        "read" => {
+            require_string(task, "path", default_inserts, ctx, diags);
+            require_string(task, "output_name", default_inserts, ctx, diags);
+            let format = task.get("format").and_then(Value::as_str).unwrap_or("text");
+            if is_literal_no_braces(format) && !["text", "json", "json5"].contains(&format) {
+                diags.push(diag(task, format!("read.format must be 'text', 'json' or 'json5', got '{format}'")));
+            }
+            if let Some(path) = task
+                .get("path")
+                .and_then(Value::as_str)
+                .filter(|p| is_literal_no_braces(p))
+            {
+                let resolved = resolve_path_ctx(ctx, path);
+                if !resolved.exists() {
+                    diags.push(warning(task, format!("read path does not exist yet: {}", resolved.display())));
+                }
+            }
+        }
+        "http_get" => {
+            require_string(task, "url", default_inserts, ctx, diags);
+            require_string(task, "output_name", default_inserts, ctx, diags);
+            require_string(task, "status_name", default_inserts, ctx, diags);
+            require_number_or_string(task, "timeout_seconds", default_inserts, ctx, diags);
+            require_object(task, "headers", default_inserts, ctx, diags);
+            for field in ["allow_error_status", "parse_json"] {
+                if task.get(field).is_some_and(|v| !v.is_boolean()) {
+                    diags.push(diag(task, format!("Field '{field}' must be a bool")));
+                }
+            }
+        }
         "write" => {
-            require_fields(task, &["item", "path"], diags);
             require_string(task, "path", default_inserts, ctx, diags);
         }
-        "show_inserts" => {}
+        "show_inserts" => {
+            require_string(task, "pattern", default_inserts, ctx, diags);
+            require_array(task, "keys", default_inserts, ctx, diags);
+        }
+        "random_int" => {
+            require_number_or_string(task, "min", default_inserts, ctx, diags);
+            require_number_or_string(task, "max", default_inserts, ctx, diags);
+            require_string(task, "output_name", default_inserts, ctx, diags);
+            let bounds = literal_int(task.get("min")).zip(literal_int(task.get("max")));
+            if let Some((min, max)) = bounds.filter(|(min, max)| min > max) {
+                diags.push(diag(task, format!("random_int.min ({min}) is greater than max ({max})")));
+            }
+            require_int_or_string(task, "seed", default_inserts, ctx, diags);
+        }
+        "random_float" => {
+            require_number_or_string(task, "min", default_inserts, ctx, diags);
+            require_number_or_string(task, "max", default_inserts, ctx, diags);
+            require_string(task, "output_name", default_inserts, ctx, diags);
+            let min = task.get("min").map_or(Some(0.0), Value::as_f64);
+            let max = task.get("max").map_or(Some(1.0), Value::as_f64);
+            if let Some((min, max)) = min.zip(max).filter(|(min, max)| min >= max) {
+                diags.push(warning(task, format!("random_float.min ({min}) is not less than max ({max})")));
+            }
+        }
         "random_choice" => {
-            require_fields(task, &["list", "output_name"], diags);
             require_array(task, "list", default_inserts, ctx, diags);
             require_string(task, "output_name", default_inserts, ctx, diags);
             if let Some(list) = get_static_array(task.get("list"), default_inserts, ctx) {
                 if list.is_empty() {
-                    diags.push(diag(task, "random_choice list is empty".to_string()));
+                    diags.push(warning(task, "random_choice list is empty".to_string()));
                 }
             }
         }
         "list_join" => {
-            require_fields(task, &["list", "before", "between", "after", "output_name"], diags);
             require_array(task, "list", default_inserts, ctx, diags);
             require_string(task, "before", default_inserts, ctx, diags);
             require_string(task, "between", default_inserts, ctx, diags);
@@ -157,7 +382,6 @@ fn validate_task(
             require_string(task, "output_name", default_inserts, ctx, diags);
         }
         "list_concat" => {
-            require_fields(task, &["lists", "output_name"], diags);
             require_array(task, "lists", default_inserts, ctx, diags);
             require_string(task, "output_name", default_inserts, ctx, diags);
             if let Some(arr) = get_static_array(task.get("lists"), default_inserts, ctx) {
@@ -181,18 +405,32 @@ fn validate_task(
                 }
             }
         }
+        "zip_lists" => {
+            require_object(task, "lists", default_inserts, ctx, diags);
+            require_string(task, "output_name", default_inserts, ctx, diags);
+            if task.get("strict").is_some_and(|v| !v.is_boolean()) {
+                diags.push(diag(task, "Field 'strict' must be a bool".to_string()));
+            }
+            if let Some(lists) = task.get("lists").and_then(Value::as_object) {
+                for (key, item) in lists {
+                    let ok = item.is_array()
+                        || is_simple_interpolation(item)
+                        || resolve_simple_value(item, default_inserts, ctx).is_some_and(|v| v.is_array());
+                    if !ok {
+                        diags.push(diag(task, format!("zip_lists.lists.{key} must be a list or a simple interpolation")));
+                    }
+                }
+            }
+        }
         "list_append" => {
-            require_fields(task, &["list", "item", "output_name"], diags);
             require_array(task, "list", default_inserts, ctx, diags);
             require_string(task, "output_name", default_inserts, ctx, diags);
         }
         "list_remove" => {
-            require_fields(task, &["list", "item", "output_name"], diags);
             require_array(task, "list", default_inserts, ctx, diags);
             require_string(task, "output_name", default_inserts, ctx, diags);
         }
         "list_index" => {
-            require_fields(task, &["list", "index", "output_name"], diags);
             require_array(task, "list", default_inserts, ctx, diags);
             require_int_or_string(task, "index", default_inserts, ctx, diags);
             require_string(task, "output_name", default_inserts, ctx, diags);
@@ -207,7 +445,6 @@ fn validate_task(
             }
         }
         "list_slice" => {
-            require_fields(task, &["list", "from_index", "to_index", "output_name"], diags);
             require_array(task, "list", default_inserts, ctx, diags);
             require_int_or_string(task, "from_index", default_inserts, ctx, diags);
             require_int_or_string(task, "to_index", default_inserts, ctx, diags);
@@ -227,27 +464,332 @@ fn validate_task(
                 }
             }
         }
+        "list_sort" => {
+            require_array(task, "list", default_inserts, ctx, diags);
+            require_string(task, "output_name", default_inserts, ctx, diags);
+            require_string(task, "order", default_inserts, ctx, diags);
+            let order = task.get("order").and_then(Value::as_str).unwrap_or("asc");
+            if is_literal_no_braces(order) && order != "asc" && order != "desc" {
+                diags.push(diag(task, format!("list_sort.order must be 'asc' or 'desc', got '{order}'")));
+            }
+        }
+        "list_reverse" => {
+            require_array(task, "list", default_inserts, ctx, diags);
+            require_string(task, "output_name", default_inserts, ctx, diags);
+        }
+        "list_unique" | "list_dedupe" => {
+            require_array(task, "list", default_inserts, ctx, diags);
+            require_string(task, "output_name", default_inserts, ctx, diags);
+        }
+        "list_shuffle" => {
+            require_array(task, "list", default_inserts, ctx, diags);
+            require_string(task, "output_name", default_inserts, ctx, diags);
+            require_int_or_string(task, "seed", default_inserts, ctx, diags);
+        }
+        "list_find" => {
+            require_array(task, "list", default_inserts, ctx, diags);
+            require_string(task, "pattern", default_inserts, ctx, diags);
+            require_string(task, "output_name", default_inserts, ctx, diags);
+            require_string(task, "index_name", default_inserts, ctx, diags);
+        }
+        "list_contains" => {
+            require_array(task, "list", default_inserts, ctx, diags);
+            require_string(task, "output_name", default_inserts, ctx, diags);
+        }
+        "list_filter" => {
+            require_array(task, "list", default_inserts, ctx, diags);
+            require_string(task, "pattern", default_inserts, ctx, diags);
+            require_string(task, "output_name", default_inserts, ctx, diags);
+            if task.get("negate").is_some_and(|v| !v.is_boolean()) {
+                diags.push(diag(task, "Field 'negate' must be a bool".to_string()));
+            }
+        }
+        "list_map" => {
+            require_array(task, "list", default_inserts, ctx, diags);
+            require_string(task, "output_name", default_inserts, ctx, diags);
+            require_array(task, "wildcard_maps", default_inserts, ctx, diags);
+            validate_wildcard_maps(task, diags);
+            if task.get("case_insensitive").is_some_and(|v| !v.is_boolean()) {
+                diags.push(diag(task, "Field 'case_insensitive' must be a bool".to_string()));
+            }
+        }
+        "list_pop" => {
+            require_array(task, "list", default_inserts, ctx, diags);
+            require_string(task, "output_name", default_inserts, ctx, diags);
+            require_string(task, "item_name", default_inserts, ctx, diags);
+            let allow_empty = task.get("allow_empty").and_then(Value::as_bool).unwrap_or(false);
+            let list = get_static_array(task.get("list"), default_inserts, ctx);
+            if !allow_empty && list.is_some_and(|l| l.is_empty()) {
+                diags.push(diag(task, "list_pop list is empty".to_string()));
+            }
+        }
+        "list_push_front" => {
+            require_array(task, "list", default_inserts, ctx, diags);
+            require_string(task, "output_name", default_inserts, ctx, diags);
+        }
+        "string_trim" => {
+            require_string(task, "text", default_inserts, ctx, diags);
+            require_string(task, "output_name", default_inserts, ctx, diags);
+            require_string(task, "mode", default_inserts, ctx, diags);
+            require_string(task, "side", default_inserts, ctx, diags);
+            require_string(task, "chars", default_inserts, ctx, diags);
+            let mode = task
+                .get("mode")
+                .or_else(|| task.get("side"))
+                .and_then(Value::as_str)
+                .unwrap_or("both");
+            if is_literal_no_braces(mode) && !["both", "left", "right"].contains(&mode) {
+                diags.push(diag(
+                    task,
+                    format!("string_trim.mode must be 'both', 'left' or 'right', got '{mode}'"),
+                ));
+            }
+        }
+        "string_upper" | "string_lower" => {
+            require_string(task, "text", default_inserts, ctx, diags);
+            require_string(task, "output_name", default_inserts, ctx, diags);
+        }
+        "string_pad" => {
+            require_string(task, "text", default_inserts, ctx, diags);
+            require_number_or_string(task, "width", default_inserts, ctx, diags);
+            require_string(task, "align", default_inserts, ctx, diags);
+            require_string(task, "pad_char", default_inserts, ctx, diags);
+            require_string(task, "output_name", default_inserts, ctx, diags);
+            let align = task.get("align").and_then(Value::as_str).unwrap_or("left");
+            if is_literal_no_braces(align) && !["left", "right", "center"].contains(&align) {
+                diags.push(diag(
+                    task,
+                    format!("string_pad.align must be 'left', 'right' or 'center', got '{align}'"),
+                ));
+            }
+            let pad_char = task.get("pad_char").and_then(Value::as_str).unwrap_or(" ");
+            if is_literal_no_braces(pad_char) && pad_char.chars().count() != 1 {
+                diags.push(diag(task, "string_pad.pad_char must be a single character".to_string()));
+            }
+        }
+        "string_slice" => {
+            require_string(task, "text", default_inserts, ctx, diags);
+            require_int_or_string(task, "from_index", default_inserts, ctx, diags);
+            require_int_or_string(task, "to_index", default_inserts, ctx, diags);
+            require_string(task, "output_name", default_inserts, ctx, diags);
+            if literal_int(task.get("from_index")) == Some(0) {
+                diags.push(diag(task, "string_slice from_index 0 is invalid (1-based)".to_string()));
+            }
+        }
+        "regex_match" => {
+            require_string(task, "text", default_inserts, ctx, diags);
+            require_string(task, "pattern", default_inserts, ctx, diags);
+            require_string(task, "output_name", default_inserts, ctx, diags);
+            require_string(task, "groups_name", default_inserts, ctx, diags);
+            require_string(task, "capture_groups", default_inserts, ctx, diags);
+            check_literal_regex(task, diags);
+        }
+        "regex_find_all" => {
+            require_string(task, "text", default_inserts, ctx, diags);
+            require_string(task, "pattern", default_inserts, ctx, diags);
+            require_string(task, "output_name", default_inserts, ctx, diags);
+            require_int_or_string(task, "capture_group", default_inserts, ctx, diags);
+            if let Some(group) = literal_int(task.get("capture_group")).filter(|group| *group < 0) {
+                diags.push(diag(task, format!("regex_find_all.capture_group cannot be negative, got {group}")));
+            }
+            check_literal_regex(task, diags);
+        }
+        "json_parse" => {
+            require_string(task, "text", default_inserts, ctx, diags);
+            require_string(task, "output_name", default_inserts, ctx, diags);
+            let format = task.get("format").and_then(Value::as_str).unwrap_or("json");
+            if is_literal_no_braces(format) && format != "json" && format != "json5" {
+                diags.push(diag(task, format!("json_parse.format must be 'json' or 'json5', got '{format}'")));
+            }
+            let on_error = task.get("on_error").and_then(Value::as_str).unwrap_or("null");
+            if is_literal_no_braces(on_error) && on_error != "null" {
+                diags.push(diag(task, format!("json_parse.on_error must be 'null', got '{on_error}'")));
+            }
+        }
+        "json_stringify" => {
+            require_string(task, "output_name", default_inserts, ctx, diags);
+            if task.get("pretty").is_some_and(|v| !v.is_boolean()) {
+                diags.push(diag(task, "Field 'pretty' must be a bool".to_string()));
+            }
+        }
+        "object_get" => {
+            require_object(task, "object", default_inserts, ctx, diags);
+            require_string(task, "path", default_inserts, ctx, diags);
+            require_string(task, "output_name", default_inserts, ctx, diags);
+        }
+        "object_set" => {
+            require_object(task, "object", default_inserts, ctx, diags);
+            require_string(task, "path", default_inserts, ctx, diags);
+            require_string(task, "output_name", default_inserts, ctx, diags);
+        }
+        "merge_objects" => {
+            require_array(task, "objects", default_inserts, ctx, diags);
+            require_string(task, "output_name", default_inserts, ctx, diags);
+            require_string(task, "mode", default_inserts, ctx, diags);
+            let mode = task.get("mode").and_then(Value::as_str).unwrap_or("shallow");
+            if is_literal_no_braces(mode) && !["shallow", "deep"].contains(&mode) {
+                diags.push(diag(task, format!("merge_objects.mode must be 'shallow' or 'deep', got '{mode}'")));
+            }
+            if let Some(arr) = get_static_array(task.get("objects"), default_inserts, ctx) {
+                let all_objects = arr.iter().all(|item| {
+                    item.is_object()
+                        || is_simple_interpolation(item)
+                        || resolve_simple_value(item, default_inserts, ctx).is_some_and(|v| v.is_object())
+                });
+                if !all_objects {
+                    diags.push(diag(
+                        task,
+                        "merge_objects.objects must contain only objects or simple interpolations".to_string(),
+                    ));
+                }
+            }
+        }
+        "object_has_key" => {
+            require_object(task, "object", default_inserts, ctx, diags);
+            require_string(task, "key", default_inserts, ctx, diags);
+            require_string(task, "output_name", default_inserts, ctx, diags);
+        }
+        "timestamp" => {
+            require_string(task, "format", default_inserts, ctx, diags);
+            require_string(task, "output_name", default_inserts, ctx, diags);
+            let format = task
+                .get("format")
+                .and_then(Value::as_str)
+                .filter(|f| is_literal_no_braces(f) && !["unix", "unix_ms"].contains(f));
+            if let Some(format) = format {
+                use std::fmt::Write as _;
+                let mut text = String::new();
+                if write!(text, "{}", chrono::Local::now().format(format)).is_err() {
+                    diags.push(diag(task, format!("timestamp.format '{format}' is not a valid strftime format")));
+                }
+            }
+        }
+        "uuid" => {
+            require_string(task, "output_name", default_inserts, ctx, diags);
+        }
+        "base64_encode" | "base64_decode" => {
+            require_string(task, "text", default_inserts, ctx, diags);
+            require_string(task, "output_name", default_inserts, ctx, diags);
+            if cmd == "base64_decode" {
+                require_string(task, "encoding", default_inserts, ctx, diags);
+                let encoding = task.get("encoding").and_then(Value::as_str).unwrap_or("utf8");
+                if is_literal_no_braces(encoding) && !["utf8", "bytes_hex"].contains(&encoding) {
+                    diags.push(diag(task, format!("base64_decode.encoding must be 'utf8' or 'bytes_hex', got '{encoding}'")));
+                }
+            }
+        }
+        "hash" => {
+            require_string(task, "text", default_inserts, ctx, diags);
+            require_string(task, "output_name", default_inserts, ctx, diags);
+            require_string(task, "algorithm", default_inserts, ctx, diags);
+            let algorithm = task.get("algorithm").and_then(Value::as_str).unwrap_or("sha256");
+            if is_literal_no_braces(algorithm) && !["sha256", "sha1", "md5"].contains(&algorithm) {
+                diags.push(diag(task, format!("hash.algorithm must be 'sha256', 'sha1' or 'md5', got '{algorithm}'")));
+            }
+        }
+        "error" => {
+            require_string(task, "message", default_inserts, ctx, diags);
+            require_number_or_string(task, "code", default_inserts, ctx, diags);
+        }
+        "env_get" => {
+            require_string(task, "name", default_inserts, ctx, diags);
+            require_string(task, "output_name", default_inserts, ctx, diags);
+        }
+        "assert" => {
+            require_number_or_string(task, "condition", default_inserts, ctx, diags);
+            require_string(task, "message", default_inserts, ctx, diags);
+            require_string(task, "level", default_inserts, ctx, diags);
+            if task.get("warn_only").is_some_and(|v| !v.is_boolean()) {
+                diags.push(diag(task, "Field 'warn_only' must be a bool".to_string()));
+            }
+            let level = task.get("level").and_then(Value::as_str).unwrap_or("error");
+            if is_literal_no_braces(level) && level != "error" && level != "warn" {
+                diags.push(diag(task, format!("assert.level must be 'error' or 'warn', got '{level}'")));
+            }
+        }
+        "type_of" => {
+            require_string(task, "output_name", default_inserts, ctx, diags);
+            require_string(task, "expected", default_inserts, ctx, diags);
+            let expected = task.get("expected").and_then(Value::as_str).unwrap_or("string");
+            if is_literal_no_braces(expected) && !TYPE_NAMES.contains(&expected) {
+                diags.push(diag(
+                    task,
+                    format!("type_of.expected must be one of {}, got '{expected}'", TYPE_NAMES.join(", ")),
+                ));
+            }
+        }
+        "coerce" => {
+            require_string(task, "to_type", default_inserts, ctx, diags);
+            require_string(task, "output_name", default_inserts, ctx, diags);
+            let to_type = task.get("to_type").and_then(Value::as_str).unwrap_or("string");
+            if is_literal_no_braces(to_type) && !COERCE_TYPES.contains(&to_type) {
+                diags.push(diag(
+                    task,
+                    format!("coerce.to_type must be one of {}, got '{to_type}'", COERCE_TYPES.join(", ")),
+                ));
+            }
+        }
+        "string_split" => {
+            require_string(task, "text", default_inserts, ctx, diags);
+            require_string(task, "separator", default_inserts, ctx, diags);
+            require_string(task, "output_name", default_inserts, ctx, diags);
+            require_int_or_string(task, "limit", default_inserts, ctx, diags);
+            if let Some(limit) = literal_int(task.get("limit")).filter(|limit| *limit < 1) {
+                diags.push(diag(task, format!("string_split.limit must be at least 1, got {limit}")));
+            }
+        }
+        "string_replace" => {
+            require_string(task, "text", default_inserts, ctx, diags);
+            require_string(task, "from", default_inserts, ctx, diags);
+            require_string(task, "to", default_inserts, ctx, diags);
+            require_string(task, "output_name", default_inserts, ctx, diags);
+            require_int_or_string(task, "count", default_inserts, ctx, diags);
+            if let Some(count) = literal_int(task.get("count")).filter(|count| *count < 0) {
+                diags.push(diag(task, format!("string_replace.count cannot be negative, got {count}")));
+            }
+        }
         "user_input" => {
-            require_fields(task, &["prompt", "output_name"], diags);
             require_string(task, "prompt", default_inserts, ctx, diags);
             require_string(task, "output_name", default_inserts, ctx, diags);
+            require_string(task, "validate_pattern", default_inserts, ctx, diags);
+            require_string(task, "validate_error", default_inserts, ctx, diags);
+            if task.get("masked").is_some_and(|v| !v.is_boolean()) {
+                diags.push(diag(task, "Field 'masked' must be a bool".to_string()));
+            }
+            warn_builtin_output_name(task, diags);
+            if let Some((pattern, Err(e))) = task
+                .get("validate_pattern")
+                .and_then(Value::as_str)
+                .filter(|p| is_literal_no_braces(p))
+                .map(|p| (p, regex::Regex::new(p)))
+            {
+                diags.push(warning(task, format!("user_input.validate_pattern '{pattern}' is not a valid regex: {e}")));
+            }
         }
         "user_choice" => {
-            require_fields(task, &["list", "description", "output_name"], diags);
             require_array(task, "list", default_inserts, ctx, diags);
             require_string(task, "description", default_inserts, ctx, diags);
             require_string(task, "output_name", default_inserts, ctx, diags);
+            if task.get("multi_select").is_some_and(|v| !v.is_boolean()) {
+                diags.push(diag(task, "Field 'multi_select' must be a bool".to_string()));
+            }
+        }
+        "user_confirm" => {
+            require_string(task, "question", default_inserts, ctx, diags);
+            require_string(task, "output_name", default_inserts, ctx, diags);
+            require_string(task, "default", default_inserts, ctx, diags);
+            let default = task.get("default").and_then(Value::as_str).unwrap_or("yes");
+            if is_literal_no_braces(default) && default != "yes" && default != "no" {
+                diags.push(diag(task, format!("user_confirm.default must be 'yes' or 'no', got '{default}'")));
+            }
         }
         "await_insert" => {
-            require_fields(task, &["name"], diags);
             require_string(task, "name", default_inserts, ctx, diags);
         }
         "label" => {
-            require_fields(task, &["name"], diags);
             require_string(task, "name", default_inserts, ctx, diags);
         }
         "goto" => {
-            require_fields(task, &["name"], diags);
             require_string(task, "name", default_inserts, ctx, diags);
             if let Some(target) = task.get("name").and_then(Value::as_str) {
                 if is_literal_no_braces(target) && target != "CONTINUE" && !labels.contains(target) {
@@ -259,9 +801,11 @@ fn validate_task(
             }
         }
         "goto_map" => {
-            require_fields(task, &["text", "target_maps"], diags);
             require_string(task, "text", default_inserts, ctx, diags);
             require_array(task, "target_maps", default_inserts, ctx, diags);
+            if task.get("case_insensitive").is_some_and(|v| !v.is_boolean()) {
+                diags.push(diag(task, "Field 'case_insensitive' must be a bool".to_string()));
+            }
             if let Some(target_maps) = task.get("target_maps").and_then(Value::as_array) {
                 if target_maps.is_empty() {
                     diags.push(diag(task, "goto_map.target_maps must not be empty".to_string()));
@@ -300,11 +844,15 @@ fn validate_task(
                         }
                     }
                 }
+                let case_insensitive = task
+                    .get("case_insensitive")
+                    .and_then(Value::as_bool)
+                    .unwrap_or(false);
                 if let Some(text) = task.get("text").and_then(Value::as_str) {
                     if is_literal_no_braces(text) && !literal_keys.is_empty() {
                         let mut matched = None;
                         for (key, val) in &literal_keys {
-                            if wildcard_match(key, text) {
+                            if wildcard_match(key, text, case_insensitive) {
                                 matched = Some(val.clone());
                                 break;
                             }
@@ -326,44 +874,59 @@ fn validate_task(
                 }
             }
         }
-        "replace_map" => {
-            require_fields(task, &["item", "output_name", "wildcard_maps"], diags);
-            require_string(task, "output_name", default_inserts, ctx, diags);
-            require_array(task, "wildcard_maps", default_inserts, ctx, diags);
-            if let Some(maps) = task.get("wildcard_maps").and_then(Value::as_array) {
-                for entry in maps {
-                    let obj = match entry.as_object() {
-                        Some(o) => o,
-                        None => {
-                            diags.push(diag(task, "wildcard_maps entries must be objects".to_string()));
+        "switch" => {
+            require_string(task, "value", default_inserts, ctx, diags);
+            if let Some(value) = task.get("value").and_then(Value::as_str) {
+                ensure_balanced_interpolation(task, "value", value, diags);
+            }
+            match task.get("cases") {
+                Some(Value::Array(cases)) => {
+                    for entry in cases {
+                        let Some(obj) = entry.as_object().filter(|obj| obj.len() == 1) else {
+                            diags.push(diag(task, "switch.cases entries must be objects with 1 key".to_string()));
                             continue;
+                        };
+                        let (pattern, case_task) = obj.iter().next().unwrap();
+                        ensure_balanced_interpolation(task, "switch.cases key", pattern, diags);
+                        if !case_task.is_object() {
+                            diags.push(diag(task, format!("switch case '{pattern}' must be a task object")));
                         }
-                    };
-                    if obj.len() != 1 {
-                        diags.push(diag(task, "wildcard_maps entries must have 1 key".to_string()));
-                        continue;
-                    }
-                    let (k, v) = obj.iter().next().unwrap();
-                    ensure_balanced_interpolation(task, "wildcard_maps key", k, diags);
-                    if let Some(val) = v.as_str() {
-                        ensure_balanced_interpolation(task, "wildcard_maps value", val, diags);
-                    } else if !is_simple_interpolation(v) {
-                        diags.push(diag(task, "wildcard_maps values must be strings".to_string()));
                     }
                 }
+                Some(_) => diags.push(diag(task, "Field 'cases' must be an array".to_string())),
+                None => {}
+            }
+            if task.get("default").is_some_and(|t| !t.is_object()) {
+                diags.push(diag(task, "Field 'default' must be a task object".to_string()));
             }
         }
+        "replace_map" => {
+            require_string(task, "output_name", default_inserts, ctx, diags);
+            require_array(task, "wildcard_maps", default_inserts, ctx, diags);
+            validate_wildcard_maps(task, diags);
+        }
         "for" => {
-            require_fields(task, &["name_list_map", "tasks"], diags);
             require_object(task, "name_list_map", default_inserts, ctx, diags);
             require_task_array(task, "tasks", default_inserts, ctx, diags);
-            if let Some(map) = task.get("name_list_map").and_then(Value::as_object) {
+            let map = match task.get("name_list_map") {
+                Some(Value::Object(map)) => Some(map.clone()),
+                Some(v) => resolve_simple_value(v, default_inserts, ctx).and_then(|r| r.as_object().cloned()),
+                None => None,
+            };
+            if let Some(map) = map {
                 let mut static_lists = Vec::new();
-                for (name, value) in map {
+                for (name, value) in &map {
                     if let Some(arr) = get_static_array(Some(value), default_inserts, ctx) {
                         static_lists.push((name.clone(), arr.len()));
                         continue;
                     }
+                    if resolve_simple_value(value, default_inserts, ctx).is_some() {
+                        diags.push(diag(
+                            task,
+                            format!("for.name_list_map value for '{name}' must resolve to a list"),
+                        ));
+                        return;
+                    }
                     if value.as_str().is_some() && !is_simple_interpolation(value) {
                         diags.push(diag(
                             task,
@@ -387,12 +950,65 @@ fn validate_task(
                 }
             }
         }
+        "if" => {
+            require_number_or_string(task, "condition", default_inserts, ctx, diags);
+            for branch in ["then", "else"] {
+                if !task.get(branch).is_some_and(Value::is_object) {
+                    require_task_array(task, branch, default_inserts, ctx, diags);
+                }
+            }
+        }
+        "repeat" => {
+            require_number_or_string(task, "count", default_inserts, ctx, diags);
+            require_task_array(task, "tasks", default_inserts, ctx, diags);
+            require_string(task, "counter_name", default_inserts, ctx, diags);
+        }
+        "while" => {
+            if task.get("condition").is_some_and(|v| !v.is_string()) {
+                diags.push(diag(task, "Field 'condition' must be a string".to_string()));
+            }
+            require_task_array(task, "tasks", default_inserts, ctx, diags);
+            require_number_or_string(task, "max_iterations", default_inserts, ctx, diags);
+        }
+        "try" => {
+            require_task_array(task, "tasks", default_inserts, ctx, diags);
+            require_task_array(task, "on_error", default_inserts, ctx, diags);
+            require_string(task, "error_name", default_inserts, ctx, diags);
+        }
+        "benchmark" => {
+            require_string(task, "output_name", default_inserts, ctx, diags);
+            require_string(task, "label", default_inserts, ctx, diags);
+            if task.get("task").is_some_and(|t| !t.is_object()) {
+                diags.push(diag(task, "Field 'task' must be a task object".to_string()));
+            }
+        }
+        "retry" => {
+            require_number_or_string(task, "max_attempts", default_inserts, ctx, diags);
+            require_number_or_string(task, "delay_seconds", default_inserts, ctx, diags);
+            if task.get("task").is_some_and(|t| !t.is_object()) {
+                diags.push(diag(task, "Field 'task' must be a task object".to_string()));
+            }
+        }
+        "timeout" => {
+            require_number_or_string(task, "seconds", default_inserts, ctx, diags);
+            if task.get("task").is_some_and(|t| !t.is_object()) {
+                diags.push(diag(task, "Field 'task' must be a task object".to_string()));
+            }
+            if !task.get("on_timeout").is_some_and(Value::is_object) {
+                require_task_array(task, "on_timeout", default_inserts, ctx, diags);
+            }
+        }
         "serial" | "parallel_wait" | "parallel_race" => {
-            require_fields(task, &["tasks"], diags);
             require_task_array(task, "tasks", default_inserts, ctx, diags);
         }
+        "parallel_limit" => {
+            require_task_array(task, "tasks", default_inserts, ctx, diags);
+            require_number_or_string(task, "max_concurrent", default_inserts, ctx, diags);
+            if let Some(limit) = literal_int(task.get("max_concurrent")).filter(|limit| *limit < 1) {
+                diags.push(diag(task, format!("parallel_limit.max_concurrent must be at least 1, got {limit}")));
+            }
+        }
         "run_task" => {
-            require_fields(task, &["task_name"], diags);
             require_string(task, "task_name", default_inserts, ctx, diags);
             if let Some(name) = task.get("task_name").and_then(Value::as_str) {
                 if !named_tasks.contains(name) {
@@ -400,31 +1016,119 @@ fn validate_task(
                 }
             }
         }
+        "map" | "filter_list" => {
+            require_array(task, "list", default_inserts, ctx, diags);
+            require_string(task, "task_name", default_inserts, ctx, diags);
+            require_string(task, "item_name", default_inserts, ctx, diags);
+            require_string(task, "result_name", default_inserts, ctx, diags);
+            require_string(task, "output_name", default_inserts, ctx, diags);
+            if let Some(name) = task.get("task_name").and_then(Value::as_str).filter(|n| !named_tasks.contains(*n)) {
+                diags.push(diag(task, format!("{cmd} references unknown task '{name}'")));
+            }
+            let on_error = task.get("on_error").and_then(Value::as_str).unwrap_or("fail");
+            if is_literal_no_braces(on_error) && on_error != "skip" && on_error != "fail" {
+                diags.push(diag(task, format!("{cmd}.on_error must be 'skip' or 'fail', got '{on_error}'")));
+            }
+        }
+        "reduce" => {
+            require_array(task, "list", default_inserts, ctx, diags);
+            require_string(task, "task_name", default_inserts, ctx, diags);
+            require_string(task, "item_name", default_inserts, ctx, diags);
+            require_string(task, "accumulator_name", default_inserts, ctx, diags);
+            require_string(task, "output_name", default_inserts, ctx, diags);
+            if let Some(name) = task.get("task_name").and_then(Value::as_str).filter(|n| !named_tasks.contains(*n)) {
+                diags.push(diag(task, format!("reduce references unknown task '{name}'")));
+            }
+        }
         "delete" | "delete_except" => {
-            require_fields(task, &["wildcards"], diags);
             require_array(task, "wildcards", default_inserts, ctx, diags);
         }
         "math" => {
-            require_fields(task, &["input", "output_name"], diags);
             require_string(task, "input", default_inserts, ctx, diags);
             require_string(task, "output_name", default_inserts, ctx, diags);
         }
         "chat" => {
-            require_fields(task, &["messages", "output_name"], diags);
             require_array(task, "messages", default_inserts, ctx, diags);
             require_string(task, "output_name", default_inserts, ctx, diags);
+            require_string(task, "usage_name", default_inserts, ctx, diags);
+            require_string(task, "gbnf_grammar", default_inserts, ctx, diags);
+            require_string(task, "gbnf_file", default_inserts, ctx, diags);
+            if task.contains_key("gbnf_grammar") && task.contains_key("gbnf_file") {
+                diags.push(diag(task, "chat accepts either gbnf_grammar or gbnf_file, not both".to_string()));
+            }
+            if let Some(path) = task
+                .get("gbnf_file")
+                .and_then(Value::as_str)
+                .filter(|p| is_literal_no_braces(p))
+            {
+                let resolved = resolve_path_ctx(ctx, path);
+                if !resolved.is_file() {
+                    diags.push(diag(task, format!("gbnf_file does not exist: {}", resolved.display())));
+                }
+            }
+            if task.get("json_output").is_some_and(|v| !v.is_boolean()) {
+                diags.push(diag(task, "Field 'json_output' must be a bool".to_string()));
+            }
             validate_voice_path(task, ctx, diags);
+            if let Some(tools) = get_static_array(task.get("tools"), default_inserts, ctx) {
+                for tool in &tools {
+                    let name = tool
+                        .get("function")
+                        .and_then(|f| f.get("name"))
+                        .and_then(Value::as_str);
+                    match name {
+                        None => diags.push(diag(task, "chat.tools entries must have a function.name".to_string())),
+                        Some(name) if is_literal_no_braces(name) && !named_tasks.contains(name) => {
+                            diags.push(diag(task, format!("chat tool '{name}' has no named task of the same name")));
+                        }
+                        Some(_) => {}
+                    }
+                }
+            }
+            if let Some(sections) = task.get("sections") {
+                let valid = sections.as_object().is_some_and(|obj| {
+                    obj.values().all(|markers| {
+                        markers
+                            .as_array()
+                            .is_some_and(|arr| arr.len() == 2 && arr.iter().all(|v| v.as_str().is_some_and(|s| !s.is_empty())))
+                    })
+                });
+                if !valid {
+                    diags.push(diag(
+                        task,
+                        "Field 'sections' must map names to [start_str, stop_str] pairs of non-empty strings".to_string(),
+                    ));
+                }
+                if task.contains_key("start_str") || task.contains_key("stop_str") {
+                    diags.push(diag(
+                        task,
+                        "Field 'sections' cannot be combined with start_str and stop_str".to_string(),
+                    ));
+                }
+            }
             if let Some(msgs) = get_static_array(task.get("messages"), default_inserts, ctx) {
                 for msg in msgs {
                     let Some(obj) = msg.as_object() else { continue };
                     if let Some(content) = obj.get("content").and_then(Value::as_str) {
                         ensure_balanced_interpolation(task, "chat.messages.content", content, diags);
                     }
+                    let image_path = obj
+                        .get("content_image_path")
+                        .and_then(Value::as_str)
+                        .filter(|p| is_literal_no_braces(p));
+                    if let Some(path) = image_path {
+                        let resolved = resolve_path_ctx(ctx, path);
+                        if !resolved.exists() {
+                            diags.push(warning(
+                                task,
+                                format!("content_image_path does not exist yet: {}", resolved.display()),
+                            ));
+                        }
+                    }
                 }
             }
         }
         "speak" => {
-            require_fields(task, &["text", "voice_path"], diags);
             require_string(task, "text", default_inserts, ctx, diags);
             require_string(task, "voice_path", default_inserts, ctx, diags);
             validate_voice_path(task, ctx, diags);
@@ -444,6 +1148,32 @@ fn validate_task(
     }
 }
 
+fn validate_wildcard_maps(task: &Task, diags: &mut Vec<Diagnostic>) {
+    let Some(maps) = task.get("wildcard_maps").and_then(Value::as_array) else {
+        return;
+    };
+    for entry in maps {
+        let obj = match entry.as_object() {
+            Some(o) => o,
+            None => {
+                diags.push(diag(task, "wildcard_maps entries must be objects".to_string()));
+                continue;
+            }
+        };
+        if obj.len() != 1 {
+            diags.push(diag(task, "wildcard_maps entries must have 1 key".to_string()));
+            continue;
+        }
+        let (k, v) = obj.iter().next().unwrap();
+        ensure_balanced_interpolation(task, "wildcard_maps key", k, diags);
+        if let Some(val) = v.as_str() {
+            ensure_balanced_interpolation(task, "wildcard_maps value", val, diags);
+        } else if !is_simple_interpolation(v) {
+            diags.push(diag(task, "wildcard_maps values must be strings".to_string()));
+        }
+    }
+}
+
 fn has_null_map_entry(task: &Task, field: &str) -> bool {
     let Some(arr) = task.get(field).and_then(Value::as_array) else {
         return false;
@@ -493,17 +1223,19 @@ fn resolve_path_ctx(ctx: &ProgramLoadContext, path: &str) -> PathBuf {
     }
 }
 
-fn wildcard_match(pattern: &str, s: &str) -> bool {
+fn wildcard_match(pattern: &str, s: &str, case_insensitive: bool) -> bool {
     let mut regex = String::from("^");
     for ch in pattern.chars() {
         match ch {
             '*' => regex.push_str(".*"),
+            '?' => regex.push_str(r"[\s\S]"),
             _ => regex.push_str(&regex::escape(&ch.to_string())),
         }
     }
     regex.push('$');
     regex::RegexBuilder::new(&regex)
         .dot_matches_new_line(true)
+        .case_insensitive(case_insensitive)
         .build()
         .map(|re| re.is_match(s))
         .unwrap_or(false)
@@ -689,6 +1421,205 @@ fn resolve_simple_value(
     get_interpdata(default_inserts, &key, ctx).ok()
 }
 
+/// Reachability over `program.order`: each task is a node that falls through to the next one
+/// (except `goto`) and jumps to the labels its own or nested gotos name. Labels no goto targets and
+/// tasks no path from the first task reaches produce warnings. A goto with an interpolated target
+/// could land on any label, so it marks every label as targeted.
+/// A `goto` with a literal target never falls through; `CONTINUE` and interpolated names might.
+fn is_hard_goto(task: &Task) -> bool {
+    task.get("cmd").and_then(Value::as_str) == Some("goto")
+        && task
+            .get("name")
+            .and_then(Value::as_str)
+            .is_some_and(|name| is_literal_no_braces(name) && name != "CONTINUE")
+}
+
+fn warn_unreachable(program: &Program, diags: &mut Vec<Diagnostic>) {
+    let order = &program.order;
+    let mut label_index = HashMap::new();
+    for (i, task) in order.iter().enumerate() {
+        let label = task.get("name").and_then(Value::as_str).filter(|_| task.get("cmd").and_then(Value::as_str) == Some("label"));
+        if let Some(name) = label {
+            label_index.entry(name.to_string()).or_insert(i);
+        }
+    }
+
+    let mut jumps = Vec::with_capacity(order.len());
+    let mut targeted = HashSet::new();
+    let mut any_dynamic = false;
+    for task in order {
+        let mut targets = HashSet::new();
+        let mut dynamic = false;
+        collect_jump_targets(&Value::Object(task.clone()), &mut targets, &mut dynamic);
+        targeted.extend(targets.iter().cloned());
+        any_dynamic |= dynamic;
+        jumps.push((targets, dynamic));
+    }
+    // Gotos inside named tasks propagate out of run_task, so they may target labels in order too.
+    let mut named_targets = HashSet::new();
+    for task in program.named_tasks.values() {
+        collect_jump_targets(&Value::Object(task.clone()), &mut named_targets, &mut any_dynamic);
+    }
+    targeted.extend(named_targets.iter().cloned());
+
+    let mut reachable = vec![false; order.len()];
+    let mut queue: Vec<usize> = named_targets.iter().filter_map(|t| label_index.get(t).copied()).collect();
+    if !order.is_empty() {
+        queue.push(0);
+    }
+    while let Some(i) = queue.pop() {
+        if reachable[i] {
+            continue;
+        }
+        reachable[i] = true;
+        let (targets, dynamic) = &jumps[i];
+        if !is_hard_goto(&order[i]) && i + 1 < order.len() {
+            queue.push(i + 1);
+        }
+        if *dynamic {
+            queue.extend(label_index.values().copied());
+        }
+        queue.extend(targets.iter().filter_map(|t| label_index.get(t).copied()));
+    }
+
+    for (i, task) in order.iter().enumerate() {
+        if !reachable[i] && (i == 0 || reachable[i - 1]) {
+            diags.push(warning(task, "Task is unreachable: no goto leads here".to_string()));
+        }
+        let untargeted_label = task
+            .get("name")
+            .and_then(Value::as_str)
+            .filter(|n| !any_dynamic && task.get("cmd").and_then(Value::as_str) == Some("label") && !targeted.contains(*n));
+        if let Some(name) = untargeted_label {
+            diags.push(warning(task, format!("Label '{name}' is never targeted by goto or goto_map")));
+        }
+    }
+}
+
+/// Commands that run a named task by its `task_name`.
+const NAMED_TASK_CMDS: &[&str] = &["run_task", "map", "filter_list", "reduce"];
+
+/// Reports named tasks that (indirectly) run themselves, which would recurse until the stack overflows.
+fn check_task_cycles(program: &Program, diags: &mut Vec<Diagnostic>) {
+    let mut names: Vec<&String> = program.named_tasks.keys().collect();
+    names.sort();
+    let edges: HashMap<&str, Vec<String>> = names
+        .iter()
+        .map(|name| {
+            let mut calls = Vec::new();
+            collect_task_calls(&Value::Object(program.named_tasks[*name].clone()), &mut calls);
+            calls.retain(|c| program.named_tasks.contains_key(c));
+            calls.sort();
+            calls.dedup();
+            (name.as_str(), calls)
+        })
+        .collect();
+
+    let mut done = HashSet::new();
+    let mut reported = HashSet::new();
+    for name in names {
+        let mut path = vec![name.clone()];
+        find_cycles(&edges, &mut path, &mut done, &mut |cycle| {
+            let mut key = cycle.to_vec();
+            key.sort();
+            if reported.insert(key) {
+                let task = &program.named_tasks[&cycle[0]];
+                diags.push(diag(
+                    task,
+                    format!("run_task cycle: {} -> {}", cycle.join(" -> "), cycle[0]),
+                ));
+            }
+        });
+    }
+}
+
+/// Depth-first search from the last task in `path`; calls `report` with each cycle found.
+fn find_cycles(
+    edges: &HashMap<&str, Vec<String>>,
+    path: &mut Vec<String>,
+    done: &mut HashSet<String>,
+    report: &mut dyn FnMut(&[String]),
+) {
+    let current = path.last().cloned().unwrap_or_default();
+    if done.contains(&current) {
+        return;
+    }
+    for next in edges.get(current.as_str()).into_iter().flatten() {
+        if let Some(start) = path.iter().position(|p| p == next) {
+            report(&path[start..]);
+        } else {
+            path.push(next.clone());
+            find_cycles(edges, path, done, report);
+            path.pop();
+        }
+    }
+    done.insert(current);
+}
+
+/// Collects the literal `task_name` of every command in `value` that runs a named task.
+fn collect_task_calls(value: &Value, calls: &mut Vec<String>) {
+    match value {
+        Value::Object(obj) => {
+            let runs_task = obj
+                .get("cmd")
+                .and_then(Value::as_str)
+                .is_some_and(|cmd| NAMED_TASK_CMDS.contains(&cmd));
+            if let Some(name) = obj.get("task_name").and_then(Value::as_str).filter(|_| runs_task) {
+                calls.push(name.to_string());
+            }
+            for v in obj.values() {
+                collect_task_calls(v, calls);
+            }
+        }
+        Value::Array(arr) => {
+            for v in arr {
+                collect_task_calls(v, calls);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Collects the literal targets of every `goto`/`goto_map` in `value`, including nested tasks.
+fn collect_jump_targets(value: &Value, targets: &mut HashSet<String>, dynamic: &mut bool) {
+    match value {
+        Value::Object(obj) => {
+            let mut add = |target: &str| {
+                if !is_literal_no_braces(target) {
+                    *dynamic = true;
+                } else if target != "CONTINUE" {
+                    targets.insert(target.to_string());
+                }
+            };
+            match obj.get("cmd").and_then(Value::as_str) {
+                Some("goto") => {
+                    if let Some(target) = obj.get("name").and_then(Value::as_str) {
+                        add(target);
+                    }
+                }
+                Some("goto_map") => {
+                    let entries = obj.get("target_maps").and_then(Value::as_array).into_iter().flatten();
+                    for target in entries.filter_map(Value::as_object).flat_map(|m| m.values()) {
+                        if let Some(target) = target.as_str() {
+                            add(target);
+                        }
+                    }
+                }
+                _ => {}
+            }
+            for v in obj.values() {
+                collect_jump_targets(v, targets, dynamic);
+            }
+        }
+        Value::Array(arr) => {
+            for v in arr {
+                collect_jump_targets(v, targets, dynamic);
+            }
+        }
+        _ => {}
+    }
+}
+
 fn collect_labels_for_list(tasks: &[Task], diags: &mut Vec<Diagnostic>) -> HashSet<String> {
     let mut labels = HashSet::new();
     for task in tasks {
@@ -714,6 +1645,38 @@ fn diag(task: &Task, message: String) -> Diagnostic {
             .and_then(Value::as_str)
             .map(|s| s.to_string()),
         line: task.get("line").and_then(Value::as_i64),
+        level: DiagLevel::Error,
+    }
+}
+
+/// Compiles a literal `pattern` so regex syntax errors surface before the program runs.
+fn check_literal_regex(task: &Task, diags: &mut Vec<Diagnostic>) {
+    let Some(pattern) = task.get("pattern").and_then(Value::as_str).filter(|p| is_literal_no_braces(p)) else {
+        return;
+    };
+    let unescaped = value_to_string(&recursive_unescape(Value::String(pattern.to_string())));
+    if let Err(e) = regex::Regex::new(&unescaped) {
+        diags.push(diag(task, format!("Invalid regex '{unescaped}': {e}")));
+    }
+}
+
+fn warn_builtin_output_name(task: &Task, diags: &mut Vec<Diagnostic>) {
+    if let Some(name) = task
+        .get("output_name")
+        .and_then(Value::as_str)
+        .filter(|name| BUILTIN_INSERT_KEYS.contains(name))
+    {
+        diags.push(warning(
+            task,
+            format!("output_name '{name}' is shadowed by the built-in insert of the same name"),
+        ));
+    }
+}
+
+fn warning(task: &Task, message: String) -> Diagnostic {
+    Diagnostic {
+        level: DiagLevel::Warning,
+        ..diag(task, message)
     }
 }
 
@@ -828,3 +1791,72 @@ fn is_index_out_of_bounds(idx: i64, len: usize) -> bool {
         true
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Collects `(cmd, field)` for every field that the match arms of `validate_task`
+    /// check through `require_*(task, "field", ...)` or read through `task.get("field")`.
+    fn validated_fields() -> Vec<(String, String)> {
+        let source = include_str!("analyzer.rs");
+        let start = source.find("\nfn validate_task(").expect("validate_task not found");
+        let body = &source[start + 1..];
+        let body = &body[..body.find("\n}\n").expect("validate_task end not found")];
+        let quoted = |text: &str| -> Vec<String> {
+            text.split('"').skip(1).step_by(2).map(str::to_string).collect()
+        };
+        let mut arm_cmds: Vec<String> = Vec::new();
+        // Narrower cmd list inside an `if cmd == "..." {` block, with the block's indentation.
+        let mut guard: Option<(usize, Vec<String>)> = None;
+        let mut fields = Vec::new();
+        for line in body.lines() {
+            let indent = line.len() - line.trim_start().len();
+            if indent == 8 && line.starts_with("        \"") && line.trim_end().ends_with("=> {") {
+                arm_cmds = quoted(line.split("=>").next().unwrap_or_default());
+                guard = None;
+                continue;
+            }
+            if guard.as_ref().is_some_and(|(guard_indent, _)| indent <= *guard_indent) {
+                guard = None;
+            }
+            if line.trim().starts_with("if cmd == ") {
+                let cmds = line.split("cmd == ").skip(1).flat_map(|part| quoted(part).into_iter().take(1));
+                guard = Some((indent, cmds.collect()));
+                continue;
+            }
+            let cmds = guard.as_ref().map_or(&arm_cmds, |(_, cmds)| cmds);
+            let mut found = Vec::new();
+            if let Some(rest) = line.trim_start().strip_prefix("require_") {
+                found.extend(rest.split_once("(task, \"").and_then(|(_, f)| f.split('"').next()));
+            }
+            for (_, rest) in line.match_indices("task.get(\"").map(|(i, m)| line.split_at(i + m.len())) {
+                found.extend(rest.split('"').next());
+            }
+            for field in found {
+                for cmd in cmds {
+                    fields.push((cmd.clone(), field.to_string()));
+                }
+            }
+        }
+        fields
+    }
+
+    #[test]
+    fn validated_fields_are_listed_in_task_specs() {
+        let fields = validated_fields();
+        assert!(fields.iter().any(|(cmd, field)| cmd == "print" && field == "text"));
+        let missing: Vec<String> = fields
+            .into_iter()
+            .filter(|(cmd, field)| {
+                let (_, required, optional) = TASK_SPECS
+                    .iter()
+                    .find(|(name, _, _)| name == cmd)
+                    .unwrap_or_else(|| panic!("'{cmd}' is validated but not in TASK_SPECS"));
+                !required.contains(&field.as_str()) && !optional.contains(&field.as_str())
+            })
+            .map(|(cmd, field)| format!("{cmd}.{field}"))
+            .collect();
+        assert!(missing.is_empty(), "fields missing from TASK_SPECS: {missing:?}");
+    }
+}
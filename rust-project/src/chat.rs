@@ -1,15 +1,24 @@
 use crate::filter::{InvertedFilter, OutputFilter};
 use anyhow::{anyhow, Result};
 use eventsource_stream::Eventsource;
+use futures::future::LocalBoxFuture;
 use futures::StreamExt;
 use serde_json::{json, Map, Value};
+use std::fmt;
+use std::sync::Arc;
+
+/// Upper bound on model/tool round trips in one `chat` task, so a model that keeps
+/// requesting tools cannot run forever.
+const MAX_TOOL_ROUNDS: usize = 16;
+
+/// Executes a tool call by name with its parsed arguments and resolves to the tool result.
+pub type ToolExecutor = Arc<dyn Fn(String, Value) -> LocalBoxFuture<'static, Result<Value>> + Send + Sync>;
 
-#[derive(Debug)]
 pub struct ChatArgs {
     pub messages: Vec<Map<String, Value>>,
     pub completion_args: Map<String, Value>,
-    pub start_str: String,
-    pub stop_str: String,
+    /// Start/stop marker pairs; each pair extracts into its own list of outputs.
+    pub sections: Vec<(String, String)>,
     pub hide_start_str: String,
     pub hide_stop_str: String,
     pub n_outputs: i64,
@@ -18,25 +27,59 @@ pub struct ChatArgs {
     pub extra_body: Map<String, Value>,
     pub api_url: String,
     pub api_key: String,
+    pub tool_executor: Option<ToolExecutor>,
+}
+
+impl fmt::Debug for ChatArgs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ChatArgs")
+            .field("messages", &self.messages)
+            .field("completion_args", &self.completion_args)
+            .field("sections", &self.sections)
+            .field("hide_start_str", &self.hide_start_str)
+            .field("hide_stop_str", &self.hide_stop_str)
+            .field("n_outputs", &self.n_outputs)
+            .field("shown", &self.shown)
+            .field("choices_list", &self.choices_list)
+            .field("extra_body", &self.extra_body)
+            .field("api_url", &self.api_url)
+            .field("api_key", &self.api_key)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct TokenUsage {
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+    pub total_tokens: i64,
 }
 
 pub struct ChatResult {
-    pub outputs: Vec<String>,
+    /// Extracted outputs, one list per section (a single list when no sections are set).
+    pub outputs: Vec<Vec<String>>,
     pub visual_output: String,
     pub raw: String,
+    /// Token usage summed over all requests, if the API reported it.
+    pub usage: Option<TokenUsage>,
 }
 
 pub async fn run_chat(
     args: ChatArgs,
     mut on_text: Option<&mut dyn FnMut(&str) -> Result<()>>,
 ) -> Result<ChatResult> {
-    if (!args.start_str.is_empty()) ^ (!args.stop_str.is_empty()) {
+    if args
+        .sections
+        .iter()
+        .any(|(start_str, stop_str)| start_str.is_empty() != stop_str.is_empty())
+    {
         return Err(anyhow!(
             "You can either set both start_str and stop_str or none."
         ));
     }
+    let filtering = args.sections.iter().any(|(start_str, _)| !start_str.is_empty());
     if args.choices_list.is_some() {
-        if !args.start_str.is_empty() {
+        if filtering {
             return Err(anyhow!("Filtering is not supported when using choices."));
         }
         if args.n_outputs != 1 {
@@ -85,84 +128,166 @@ pub async fn run_chat(
 
     let url = normalize_api_url(&args.api_url);
     let client = reqwest::Client::new();
-    let res = client
-        .post(url)
-        .bearer_auth(&args.api_key)
-        .json(&request)
-        .send()
-        .await?;
-
-    if !res.status().is_success() {
-        let status = res.status();
-        let body = res.text().await.unwrap_or_default();
-        return Err(anyhow!("Chat request failed: {status} {body}"));
-    }
 
-    let mut output_filter = OutputFilter::new(&args.start_str, &args.stop_str, args.n_outputs > 1);
+    let mut output_filter = OutputFilter::new(args.sections.clone(), args.n_outputs > 1);
     let mut hide_filter = InvertedFilter::new(&args.hide_start_str, &args.hide_stop_str);
     let mut raw = String::new();
     let mut visual_output = String::new();
     let mut ran_out_of_context = false;
+    let mut usage: Option<TokenUsage> = None;
+    let mut tool_rounds = 0;
 
-    let mut stream = res.bytes_stream().eventsource();
-    while let Some(event) = stream.next().await {
-        let event = event?;
-        if event.data == "[DONE]" {
-            break;
-        }
-        let chunk: Value = serde_json::from_str(&event.data)?;
-        let delta = chunk
-            .get("choices")
-            .and_then(Value::as_array)
-            .and_then(|arr| arr.first())
-            .and_then(|v| v.get("delta"))
-            .and_then(|v| v.get("content"))
-            .and_then(Value::as_str)
-            .unwrap_or("");
-        let finish_reason = chunk
-            .get("choices")
-            .and_then(Value::as_array)
-            .and_then(|arr| arr.first())
-            .and_then(|v| v.get("finish_reason"))
-            .and_then(Value::as_str);
-        if finish_reason == Some("length") {
-            ran_out_of_context = true;
+    // Text of the last model turn, which holds the answer once tool calls are done.
+    let final_content = loop {
+        let res = client
+            .post(&url)
+            .bearer_auth(&args.api_key)
+            .json(&request)
+            .send()
+            .await?;
+
+        if !res.status().is_success() {
+            let status = res.status();
+            let body = res.text().await.unwrap_or_default();
+            return Err(anyhow!("Chat request failed: {status} {body}"));
         }
-        if !delta.is_empty() {
-            raw.push_str(delta);
-            let fragment = output_filter.update(delta);
-            let visual_fragment = hide_filter.update(&fragment);
-            if args.shown && !visual_fragment.is_empty() {
-                if let Some(cb) = on_text.as_mut() {
-                    cb(&visual_fragment)?;
+
+        let mut turn_content = String::new();
+        let mut tool_calls: Vec<Map<String, Value>> = Vec::new();
+        let mut wants_tools = false;
+
+        let mut stream = res.bytes_stream().eventsource();
+        while let Some(event) = stream.next().await {
+            let event = event?;
+            if event.data == "[DONE]" {
+                break;
+            }
+            let chunk: Value = serde_json::from_str(&event.data)?;
+            if let Some(reported) = chunk.get("usage").and_then(Value::as_object) {
+                let count = |key: &str| reported.get(key).and_then(Value::as_i64).unwrap_or(0);
+                let total = usage.get_or_insert_with(TokenUsage::default);
+                total.prompt_tokens += count("prompt_tokens");
+                total.completion_tokens += count("completion_tokens");
+                total.total_tokens += count("total_tokens");
+            }
+            let choice = chunk
+                .get("choices")
+                .and_then(Value::as_array)
+                .and_then(|arr| arr.first());
+            let delta = choice
+                .and_then(|v| v.get("delta"))
+                .and_then(|v| v.get("content"))
+                .and_then(Value::as_str)
+                .unwrap_or("");
+            if let Some(calls) = choice
+                .and_then(|v| v.get("delta"))
+                .and_then(|v| v.get("tool_calls"))
+                .and_then(Value::as_array)
+            {
+                merge_tool_call_deltas(&mut tool_calls, calls);
+            }
+            let finish_reason = choice
+                .and_then(|v| v.get("finish_reason"))
+                .and_then(Value::as_str);
+            if finish_reason == Some("length") {
+                ran_out_of_context = true;
+            }
+            if finish_reason == Some("tool_calls") {
+                wants_tools = true;
+            }
+            if !delta.is_empty() {
+                raw.push_str(delta);
+                turn_content.push_str(delta);
+                let fragment = output_filter.update(delta);
+                let visual_fragment = hide_filter.update(&fragment);
+                if args.shown && !visual_fragment.is_empty() {
+                    if let Some(cb) = on_text.as_mut() {
+                        cb(&visual_fragment)?;
+                    }
+                    visual_output.push_str(&visual_fragment);
                 }
-                visual_output.push_str(&visual_fragment);
             }
         }
-    }
+
+        let executor = match args.tool_executor.as_ref() {
+            Some(executor) if wants_tools && !tool_calls.is_empty() => executor,
+            _ => break turn_content,
+        };
+        tool_rounds += 1;
+        if tool_rounds > MAX_TOOL_ROUNDS {
+            return Err(anyhow!("Chat exceeded {MAX_TOOL_ROUNDS} tool call rounds without a final answer"));
+        }
+
+        let mut messages = request
+            .get("messages")
+            .and_then(Value::as_array)
+            .cloned()
+            .unwrap_or_default();
+        messages.push(json!({
+            "role": "assistant",
+            "content": turn_content,
+            "tool_calls": tool_calls.clone(),
+        }));
+        for call in &tool_calls {
+            let id = call.get("id").and_then(Value::as_str).unwrap_or("");
+            let function = call.get("function").and_then(Value::as_object);
+            let name = function
+                .and_then(|f| f.get("name"))
+                .and_then(Value::as_str)
+                .unwrap_or("")
+                .to_string();
+            let arguments = function
+                .and_then(|f| f.get("arguments"))
+                .and_then(Value::as_str)
+                .unwrap_or("");
+            let arguments = if arguments.trim().is_empty() {
+                Value::Object(Map::new())
+            } else {
+                serde_json::from_str(arguments)
+                    .map_err(|e| anyhow!("Tool call '{name}' has invalid arguments ({e}): {arguments}"))?
+            };
+            let result = executor(name, arguments).await?;
+            let content = match result {
+                Value::String(s) => s,
+                other => serde_json::to_string(&other)?,
+            };
+            messages.push(json!({
+                "role": "tool",
+                "tool_call_id": id,
+                "content": content,
+            }));
+        }
+        request.insert("messages".to_string(), Value::Array(messages));
+    };
 
     if ran_out_of_context {
         return Err(anyhow!("Generation exceeded context length."));
     }
 
     if let Some(_) = args.choices_list {
-        let parsed: Value = serde_json::from_str(&raw)?;
+        let parsed: Value = serde_json::from_str(&final_content)?;
         let choice = parsed
             .get("choice")
             .and_then(Value::as_str)
             .ok_or_else(|| anyhow!("Choice schema response missing 'choice'"))?;
         return Ok(ChatResult {
-            outputs: vec![choice.to_string()],
+            outputs: vec![vec![choice.to_string()]],
             visual_output,
             raw,
+            usage,
         });
     }
 
-    let outputs = output_filter.outputs().into_iter().map(|o| o.trim().to_string()).collect();
+    let outputs = output_filter
+        .outputs()
+        .into_iter()
+        .map(|bucket| bucket.into_iter().map(|o| o.trim().to_string()).collect())
+        .collect();
     Ok(ChatResult {
         outputs,
         visual_output,
         raw,
+        usage,
     })
 }
 
@@ -175,6 +300,40 @@ fn normalize_api_url(api_url: &str) -> String {
     }
 }
 
+fn merge_tool_call_deltas(calls: &mut Vec<Map<String, Value>>, deltas: &[Value]) {
+    for delta in deltas {
+        let index = delta
+            .get("index")
+            .and_then(Value::as_u64)
+            .map(|i| i as usize)
+            .unwrap_or(calls.len());
+        while calls.len() <= index {
+            let mut call = Map::new();
+            call.insert("id".to_string(), Value::String(String::new()));
+            call.insert("type".to_string(), Value::String("function".to_string()));
+            call.insert("function".to_string(), json!({"name": "", "arguments": ""}));
+            calls.push(call);
+        }
+        let call = &mut calls[index];
+        if let Some(id) = delta.get("id").and_then(Value::as_str) {
+            call.insert("id".to_string(), Value::String(id.to_string()));
+        }
+        let Some(function) = delta.get("function") else {
+            continue;
+        };
+        let Some(target) = call.get_mut("function").and_then(Value::as_object_mut) else {
+            continue;
+        };
+        for key in ["name", "arguments"] {
+            if let Some(part) = function.get(key).and_then(Value::as_str) {
+                let mut current = target.get(key).and_then(Value::as_str).unwrap_or("").to_string();
+                current.push_str(part);
+                target.insert(key.to_string(), Value::String(current));
+            }
+        }
+    }
+}
+
 fn map_message(role: &str, content: &str) -> Map<String, Value> {
     let mut m = Map::new();
     m.insert("role".to_string(), Value::String(role.to_string()));
@@ -0,0 +1,22 @@
+use anyhow::{anyhow, Result};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+
+/// Encodes bytes with standard (RFC 4648) base64, including padding.
+pub fn encode_base64(bytes: &[u8]) -> String {
+    STANDARD.encode(bytes)
+}
+
+/// Decodes standard base64 and renders the bytes according to `encoding`:
+/// `"utf8"` requires valid UTF-8 text, `"bytes_hex"` gives lowercase hex.
+pub fn decode_base64(text: &str, encoding: &str) -> Result<String> {
+    let bytes = STANDARD
+        .decode(text.trim())
+        .map_err(|e| anyhow!("base64_decode input is not valid base64: {e}"))?;
+    match encoding {
+        "utf8" => String::from_utf8(bytes)
+            .map_err(|e| anyhow!("base64_decode output is not valid UTF-8 ({e}); use encoding 'bytes_hex'")),
+        "bytes_hex" => Ok(bytes.iter().map(|b| format!("{b:02x}")).collect()),
+        other => Err(anyhow!("base64_decode.encoding must be 'utf8' or 'bytes_hex', got '{other}'")),
+    }
+}
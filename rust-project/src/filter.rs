@@ -1,65 +1,94 @@
+/// Extracts the text between start/stop marker pairs. Each pair collects into its own bucket,
+/// so a single stream can be split into several named sections. Without sections, or when every
+/// pair is empty, everything goes into one bucket.
 pub struct OutputFilter {
-    start_str: String,
-    stop_str: String,
+    sections: Vec<(String, String)>,
+    filtering: bool,
     enumerate_outputs: bool,
     buffer: String,
-    shown: bool,
-    outputs: Vec<String>,
+    active: Option<usize>,
+    outputs: Vec<Vec<String>>,
 }
 
 impl OutputFilter {
-    pub fn new(start_str: &str, stop_str: &str, enumerate_outputs: bool) -> Self {
+    pub fn new(sections: Vec<(String, String)>, enumerate_outputs: bool) -> Self {
+        // Buckets stay positional, one per pair; an empty pair never matches and keeps an empty bucket.
+        let filtering = sections.iter().any(|(start, stop)| !start.is_empty() && !stop.is_empty());
+        let buckets = sections.len().max(1);
         Self {
-            start_str: start_str.to_string(),
-            stop_str: stop_str.to_string(),
+            sections,
+            filtering,
             enumerate_outputs,
             buffer: String::new(),
-            shown: false,
-            outputs: Vec::new(),
+            active: None,
+            outputs: vec![Vec::new(); buckets],
         }
     }
 
     pub fn update(&mut self, chunk: &str) -> String {
-        if self.start_str.is_empty() || self.stop_str.is_empty() {
-            if self.outputs.is_empty() {
-                self.outputs.push(String::new());
+        if !self.filtering {
+            if self.outputs[0].is_empty() {
+                self.outputs[0].push(String::new());
             }
-            self.outputs.last_mut().unwrap().push_str(chunk);
+            self.outputs[0].last_mut().unwrap().push_str(chunk);
             return chunk.to_string();
         }
 
         self.buffer.push_str(chunk);
-        let next_str = if self.shown { &self.stop_str } else { &self.start_str };
         let mut enumeration = String::new();
-        if self.buffer.starts_with(next_str) && !next_str.is_empty() {
-            self.buffer = self.buffer[next_str.len()..].to_string();
-            self.shown = !self.shown;
-            if self.shown {
-                self.outputs.push(String::new());
-                if self.enumerate_outputs {
-                    if self.outputs.len() > 1 {
-                        enumeration.push_str("\n\n");
+        match self.active {
+            Some(idx) => {
+                let stop_str = &self.sections[idx].1;
+                if self.buffer.starts_with(stop_str.as_str()) {
+                    self.buffer = self.buffer[stop_str.len()..].to_string();
+                    self.active = None;
+                }
+            }
+            None => {
+                let matched = self
+                    .sections
+                    .iter()
+                    .position(|(start_str, _)| !start_str.is_empty() && self.buffer.starts_with(start_str.as_str()));
+                if let Some(idx) = matched {
+                    self.buffer = self.buffer[self.sections[idx].0.len()..].to_string();
+                    self.active = Some(idx);
+                    let bucket = &mut self.outputs[idx];
+                    bucket.push(String::new());
+                    if self.enumerate_outputs {
+                        if bucket.len() > 1 {
+                            enumeration.push_str("\n\n");
+                        }
+                        enumeration.push_str(&format!("{}. ", bucket.len()));
                     }
-                    enumeration.push_str(&format!("{}. ", self.outputs.len()));
                 }
             }
         }
 
-        let safe = safe_index(&self.buffer, next_str);
+        let safe = match self.active {
+            Some(idx) => safe_index(&self.buffer, &self.sections[idx].1),
+            None => self
+                .sections
+                .iter()
+                .filter(|(start_str, _)| !start_str.is_empty())
+                .map(|(start_str, _)| safe_index(&self.buffer, start_str))
+                .min()
+                .unwrap_or(self.buffer.len()),
+        };
 
-        let delta = if self.shown {
+        let delta = if self.active.is_some() {
             self.buffer[..safe].to_string()
         } else {
             String::new()
         };
         self.buffer = self.buffer[safe..].to_string();
-        if self.shown && !self.outputs.is_empty() {
-            self.outputs.last_mut().unwrap().push_str(&delta);
+        if let Some(output) = self.active.and_then(|idx| self.outputs[idx].last_mut()) {
+            output.push_str(&delta);
         }
         format!("{enumeration}{delta}")
     }
 
-    pub fn outputs(&self) -> Vec<String> {
+    /// One list of extracted outputs per section, in the order the sections were given.
+    pub fn outputs(&self) -> Vec<Vec<String>> {
         self.outputs.clone()
     }
 }
@@ -114,3 +143,32 @@ fn safe_index(buffer: &str, next_str: &str) -> usize {
     }
     safe
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pair(start: &str, stop: &str) -> (String, String) {
+        (start.to_string(), stop.to_string())
+    }
+
+    #[test]
+    fn empty_pair_keeps_its_own_bucket() {
+        let mut filter = OutputFilter::new(vec![pair("<a>", "</a>"), pair("", ""), pair("<c>", "</c>")], false);
+        for ch in "<a>one</a> <c>three</c>".chars() {
+            filter.update(&ch.to_string());
+        }
+        assert_eq!(
+            filter.outputs(),
+            vec![vec!["one".to_string()], Vec::new(), vec!["three".to_string()]]
+        );
+    }
+
+    #[test]
+    fn only_empty_pairs_pass_everything_through() {
+        let mut filter = OutputFilter::new(vec![pair("", "")], false);
+        assert_eq!(filter.update("hello "), "hello ");
+        filter.update("world");
+        assert_eq!(filter.outputs(), vec![vec!["hello world".to_string()]]);
+    }
+}
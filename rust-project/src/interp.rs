@@ -45,10 +45,10 @@ pub fn interpolate_inserts(
     if let Some(insertkey) = get_simple_insertkey(&s) {
         if let Some(subkey) = get_simple_insertkey(&insertkey) {
             let inner = interpolate_inserts(inserts, &format!("{}{}{}", INSERT_START, subkey, INSERT_STOP), ctx)?;
-            return get_interpdata(inserts, &value_to_string(&inner), ctx);
+            return get_filtered_interpdata(inserts, &value_to_string(&inner), ctx);
         }
         let inner = interpolate_inserts(inserts, &insertkey, ctx)?;
-        return get_interpdata(inserts, &value_to_string(&inner), ctx);
+        return get_filtered_interpdata(inserts, &value_to_string(&inner), ctx);
     }
 
     while s.contains(INSERT_START) {
@@ -67,10 +67,11 @@ pub fn interpolate_inserts(
         let inner = s[outer_from + 1..inner_to]
             .replace(&replaced_start, &escaped_start)
             .replace(&replaced_stop, &escaped_stop);
-        let insert_value = get_interpdata(inserts, &inner, ctx)?;
+        let insert_value = get_filtered_interpdata(inserts, &inner, ctx)?;
         let insert_str = match insert_value {
             Value::String(ref x) => x.clone(),
             Value::Number(ref n) => n.to_string(),
+            Value::Bool(b) => b.to_string(),
             Value::Array(ref arr) => arr.iter().map(value_to_string).collect::<Vec<_>>().join(""),
             _ => {
                 return Err(anyhow!(
@@ -88,6 +89,47 @@ pub fn interpolate_inserts(
     Ok(Value::String(s))
 }
 
+type InsertFilter = fn(Value) -> Result<Value>;
+
+/// Filters that can be applied to an insert with `{key|filter}`.
+const INSERT_FILTERS: &[(&str, InsertFilter)] = &[("length", filter_length)];
+
+fn filter_length(value: Value) -> Result<Value> {
+    let len = match &value {
+        Value::String(s) => s.chars().count(),
+        Value::Array(arr) => arr.len(),
+        Value::Object(obj) => obj.len(),
+        _ => return Err(anyhow!("length filter requires a string, list or object")),
+    };
+    Ok(Value::String(len.to_string()))
+}
+
+/// Like `get_interpdata`, but applies `|filter` suffixes from left to right.
+/// Keys without `|` (or keys that exist verbatim) are looked up unchanged.
+fn get_filtered_interpdata(
+    inserts: &Map<String, Value>,
+    insertkey: &str,
+    ctx: &ProgramLoadContext,
+) -> Result<Value> {
+    if !insertkey.contains('|') || inserts.contains_key(insertkey) {
+        return get_interpdata(inserts, insertkey, ctx);
+    }
+    let mut parts = insertkey.split('|');
+    let key = parts.next().unwrap_or_default().trim();
+    let mut value = get_interpdata(inserts, key, ctx)?;
+    for name in parts.map(str::trim) {
+        let filter = INSERT_FILTERS
+            .iter()
+            .find(|(filter_name, _)| *filter_name == name)
+            .map(|(_, filter)| filter)
+            .ok_or_else(|| anyhow!("Unknown interpolation filter '{name}' in '{insertkey}'"))?;
+        value = filter(value).map_err(|e| anyhow!("Could not apply filter to '{key}': {e}"))?;
+    }
+    Ok(value)
+}
+
+pub const BUILTIN_INSERT_KEYS: &[&str] = &["HH:MM", "HH:MM:SS", "DATE", "UNIX_TIME", "RANDOM_UUID"];
+
 pub fn get_interpdata(
     inserts: &Map<String, Value>,
     insertkey: &str,
@@ -102,6 +144,12 @@ pub fn get_interpdata(
             let now = Local::now();
             return Ok(Value::String(now.format("%H:%M:%S").to_string()));
         }
+        "DATE" => {
+            let now = Local::now();
+            return Ok(Value::String(now.format("%Y-%m-%d").to_string()));
+        }
+        "UNIX_TIME" => return Ok(Value::String(Local::now().timestamp().to_string())),
+        "RANDOM_UUID" => return Ok(Value::String(uuid::Uuid::new_v4().to_string())),
         "" => return Err(anyhow!("Tried to interpolate empty string ''")),
         _ => {}
     }
@@ -119,7 +167,17 @@ pub fn get_interpdata(
         return Ok(v.clone());
     }
 
-    if let Some(dir) = ctx.inserts_dir.as_ref() {
+    // Only walk into containers, and fall back to the inserts dirs when the path is
+    // missing, so keys like `notes.txt` still resolve to files.
+    let nested = insertkey
+        .split_once('.')
+        .and_then(|(root, path)| inserts.get(root).map(|v| (root, path, v)))
+        .filter(|(_, _, v)| v.is_object() || v.is_array());
+    if let Some(found) = nested.and_then(|(_, path, v)| value_at_path(v, path)) {
+        return Ok(found.clone());
+    }
+
+    for dir in &ctx.inserts_dirs {
         let json5_path = dir.join(format!("{insertkey}.json5"));
         if json5_path.exists() {
             let raw = fs::read_to_string(&json5_path)?;
@@ -133,9 +191,24 @@ pub fn get_interpdata(
         }
     }
 
+    if let Some((root, path, _)) = nested {
+        return Err(anyhow!("Could not find '{path}' in variable '{root}'"));
+    }
     Err(anyhow!("Could not find variable '{insertkey}'"))
 }
 
+pub fn value_at_path<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.').try_fold(value, |current, segment| match current {
+        Value::Object(obj) => obj.get(segment),
+        Value::Array(arr) => segment
+            .parse::<usize>()
+            .ok()
+            .filter(|idx| *idx > 0)
+            .and_then(|idx| arr.get(idx - 1)),
+        _ => None,
+    })
+}
+
 pub fn set_interpdata(inserts: &mut Map<String, Value>, key: &str, value: Value) {
     inserts.insert(key.to_string(), value);
 }
@@ -207,12 +280,32 @@ pub fn recursive_interpolate(
         )),
         Value::Object(obj) => {
             if let Some(cmd) = obj.get("cmd").and_then(Value::as_str) {
-                if cmd == "goto_map" || cmd == "replace_map" {
+                if cmd == "goto_map" || cmd == "replace_map" || cmd == "list_map" || cmd == "switch" {
                     return Ok(Value::Object(obj));
                 }
-                if cmd == "for" || cmd == "serial" || cmd == "parallel_wait" || cmd == "parallel_race" {
+                let task_fields: &[&str] = match cmd {
+                    "for" | "while" | "repeat" | "serial" | "parallel_wait" | "parallel_race"
+                    | "parallel_limit" => &["tasks"],
+                    "if" => &["then", "else"],
+                    "try" => &["tasks", "on_error"],
+                    "benchmark" | "retry" => &["task"],
+                    "timeout" => &["task", "on_timeout"],
+                    _ => &[],
+                };
+                if !task_fields.is_empty() {
+                    // benchmark and try read plain fields next to their nested tasks; those are
+                    // interpolated now, the nested tasks only when they run.
+                    let interpolate_plain_fields = cmd == "benchmark" || cmd == "try";
                     let mut obj = obj;
-                    if let Some(tasks_val) = obj.get_mut("tasks") {
+                    if interpolate_plain_fields {
+                        for (key, field) in obj.iter_mut() {
+                            if key != "cmd" && !task_fields.contains(&key.as_str()) {
+                                *field = recursive_interpolate(inserts, field.take(), ctx)?;
+                            }
+                        }
+                    }
+                    for field in task_fields {
+                        let Some(tasks_val) = obj.get_mut(*field) else { continue };
                         if let Some(s) = tasks_val.as_str() {
                             if let Some(insertkey) = get_simple_insertkey(s) {
                                 let v = get_interpdata(inserts, &insertkey, ctx)?;
@@ -320,3 +413,49 @@ pub fn value_to_string(value: &Value) -> String {
         Value::Object(_) | Value::Null => serde_json::to_string(value).unwrap_or_default(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::path::PathBuf;
+
+    fn test_ctx() -> ProgramLoadContext {
+        ProgramLoadContext::new(PathBuf::from("test.json5"), Vec::new()).unwrap()
+    }
+
+    fn test_inserts() -> Map<String, Value> {
+        json!({"key": "timing", "name": "bench one", "x": "outer"})
+            .as_object()
+            .cloned()
+            .unwrap()
+    }
+
+    #[test]
+    fn benchmark_interpolates_output_name_but_not_nested_task() {
+        let task = json!({
+            "cmd": "benchmark",
+            "output_name": "{key}",
+            "label": "{name}",
+            "task": {"cmd": "print", "text": "{x}"},
+        });
+        let result = recursive_interpolate(&test_inserts(), task, &test_ctx()).unwrap();
+        assert_eq!(result["output_name"], json!("timing"));
+        assert_eq!(result["label"], json!("bench one"));
+        assert_eq!(result["task"]["text"], json!("{x}"));
+    }
+
+    #[test]
+    fn try_interpolates_error_name_but_not_nested_tasks() {
+        let task = json!({
+            "cmd": "try",
+            "error_name": "{key}",
+            "tasks": [{"cmd": "print", "text": "{x}"}],
+            "on_error": [{"cmd": "print", "text": "{x}"}],
+        });
+        let result = recursive_interpolate(&test_inserts(), task, &test_ctx()).unwrap();
+        assert_eq!(result["error_name"], json!("timing"));
+        assert_eq!(result["tasks"][0]["text"], json!("{x}"));
+        assert_eq!(result["on_error"][0]["text"], json!("{x}"));
+    }
+}
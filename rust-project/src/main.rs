@@ -1,5 +1,6 @@
 mod analyzer;
 mod chat;
+mod codec;
 mod filter;
 mod interp;
 mod math;
@@ -11,13 +12,19 @@ mod audio_web;
 mod ui;
 
 use anyhow::Result;
-use clap::Parser;
+use clap::{ArgAction, CommandFactory, Parser};
 use model::{Program, ProgramLoadContext};
+use serde_json::Value;
+use std::ffi::OsString;
 use std::path::PathBuf;
 
+/// Config files that supply default argument values, lowest precedence first.
+const CONFIG_PATHS: &[&str] = &["~/.config/interpolation-engine/config.json5", "./interpolation-engine.json5"];
+
 #[derive(Parser, Debug)]
 #[command(name = "interpolation_engine")]
 #[command(about = "Run an interpolation-engine program.", long_about = None)]
+#[command(args_override_self = true)]
 struct Args {
     /// Path to the .json5 program file.
     program: Option<PathBuf>,
@@ -30,9 +37,9 @@ struct Args {
     /// Path to store input history at. (Reserved for future use)
     #[arg(long)]
     history: Option<PathBuf>,
-    /// Optional directory to load inserts from when a key is not found in state['inserts'].
-    #[arg(long = "inserts-dir")]
-    inserts_dir: Option<PathBuf>,
+    /// Directories to load inserts from when a key is not found in state['inserts'], searched in order.
+    #[arg(long = "inserts-dir", num_args(1..))]
+    inserts_dir: Vec<PathBuf>,
     /// Enable agent mode (file-based interaction).
     #[arg(long = "agent-mode")]
     agent_mode: bool,
@@ -48,11 +55,34 @@ struct Args {
     /// Port for the local audio web server.
     #[arg(long = "audio-port", default_value_t = 8765)]
     audio_port: u16,
+    /// Maximum number of lines kept in the TUI output pane (default: unlimited).
+    #[arg(long = "output-buffer-lines", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    output_buffer_lines: Option<usize>,
+    /// Do not print analyzer warnings.
+    #[arg(long = "no-warn")]
+    no_warn: bool,
+    /// Load and check the program without running it. Exits with 1 if validation fails.
+    #[arg(long = "validate-only", visible_alias = "check")]
+    validate_only: bool,
+    /// Run the program without side effects: no file writes, HTTP requests, chat calls, speech or prompts.
+    #[arg(long = "dry-run")]
+    dry_run: bool,
+    /// Print every task command with its required and optional fields, then exit.
+    #[arg(long = "list-cmds")]
+    list_cmds: bool,
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let args = Args::parse();
+    let mut argv: Vec<OsString> = std::env::args_os().collect();
+    let defaults = config_args(&argv);
+    argv.splice(1..1, defaults.into_iter().map(OsString::from));
+    let args = Args::parse_from(argv);
+
+    if args.list_cmds {
+        print_task_specs();
+        return Ok(());
+    }
 
     if args.program.is_none() {
         eprintln!("Error: specify a program (.json5 file) to run.");
@@ -60,14 +90,20 @@ async fn main() -> Result<()> {
     }
 
     let program_path = args.program.unwrap();
-    let inserts_dir = args.inserts_dir.clone();
-
-    let mut load_ctx = ProgramLoadContext::new(program_path.clone(), inserts_dir.clone())?;
+    let mut load_ctx = ProgramLoadContext::new(program_path.clone(), args.inserts_dir.clone())?;
     let mut program: Program = parser::load_program(&mut load_ctx)?;
 
-    analyzer::analyze_program(&program, &load_ctx)?;
+    let warnings = analyzer::analyze_program(&program, &load_ctx)?;
+    if !args.no_warn {
+        for warning in warnings {
+            eprintln!("Warning: {}", analyzer::format_diagnostic(&warning));
+        }
+    }
+    if args.validate_only {
+        return Ok(());
+    }
 
-    runtime::run_program(
+    let result = runtime::run_program(
         &mut program,
         &load_ctx,
         &args.program_arguments,
@@ -79,9 +115,95 @@ async fn main() -> Result<()> {
             history_path: args.history,
             audio_web: args.audio_web,
             audio_port: args.audio_port,
+            output_buffer_lines: args.output_buffer_lines,
+            dry_run: args.dry_run,
         },
     )
-    .await?;
+    .await;
+
+    if let Err(err) = result {
+        if let Some(program_error) = err.downcast_ref::<runtime::ProgramError>() {
+            eprintln!("Error: {}", program_error.message);
+            std::process::exit(program_error.code);
+        }
+        return Err(err);
+    }
 
     Ok(())
 }
+
+fn print_task_specs() {
+    let mut specs = analyzer::TASK_SPECS.to_vec();
+    specs.sort_by_key(|(name, _, _)| *name);
+    for (name, required, optional) in specs {
+        println!("{name}");
+        if !required.is_empty() {
+            println!("  required: {}", required.join(", "));
+        }
+        if !optional.is_empty() {
+            println!("  optional: {}", optional.join(", "));
+        }
+    }
+}
+
+/// Turns the config files into command-line flags that are placed before the real
+/// arguments. Options already given on the command line are skipped. Problems with a
+/// config file are reported to stderr and never stop the program.
+fn config_args(cli: &[OsString]) -> Vec<String> {
+    let mut merged = serde_json::Map::new();
+    for path in CONFIG_PATHS {
+        let path = PathBuf::from(shellexpand::tilde(path).as_ref());
+        if !path.is_file() {
+            continue;
+        }
+        let parsed = std::fs::read_to_string(&path)
+            .map_err(anyhow::Error::from)
+            .and_then(|raw| json5::from_str::<Value>(&raw).map_err(anyhow::Error::from));
+        match parsed {
+            Ok(Value::Object(obj)) => merged.extend(obj),
+            Ok(_) => eprintln!("Warning: config file {} must contain an object; ignoring it.", path.display()),
+            Err(err) => eprintln!("Warning: config file {} could not be read: {err}", path.display()),
+        }
+    }
+
+    let command = Args::command();
+    let mut args = Vec::new();
+    for (key, value) in merged {
+        let flag = key.replace('_', "-");
+        if flag == "program" {
+            eprintln!("Warning: config option 'program' is not supported; pass the program on the command line.");
+            continue;
+        }
+        let Some(arg) = command.get_arguments().find(|arg| arg.get_long() == Some(flag.as_str())) else {
+            eprintln!("Warning: unknown config option '{key}'; ignoring it.");
+            continue;
+        };
+        let given = cli
+            .iter()
+            .skip(1)
+            .take_while(|arg| *arg != "--")
+            .filter_map(|arg| arg.to_str())
+            .any(|arg| arg.strip_prefix("--").and_then(|arg| arg.strip_prefix(flag.as_str())).is_some_and(|rest| rest.is_empty() || rest.starts_with('=')));
+        if given {
+            continue;
+        }
+        let takes_value = arg.get_action().takes_values();
+        let values = match value {
+            Value::Array(items) if matches!(arg.get_action(), ArgAction::Append) => items,
+            value => vec![value],
+        };
+        for value in values {
+            match value {
+                Value::Bool(true) if !takes_value => args.push(format!("--{flag}")),
+                Value::Bool(false) if !takes_value => {}
+                Value::String(s) if takes_value => args.push(format!("--{flag}={}", shellexpand::tilde(&s))),
+                Value::Number(n) if takes_value => args.push(format!("--{flag}={n}")),
+                _ => {
+                    let expected = if takes_value { "a string or number" } else { "a bool" };
+                    eprintln!("Warning: config option '{key}' must be {expected}; ignoring it.");
+                }
+            }
+        }
+    }
+    args
+}
@@ -7,6 +7,10 @@ const LEGAL: &str = " .0123456789+-*/%^(),_";
 
 pub fn eval_math(inserts: &Map<String, Value>, input: &str, ctx: &ProgramLoadContext) -> Result<i64> {
     let interpolated = interpolate_inserts(inserts, input, ctx)?;
+    // A bool insert on its own (e.g. a regex_match result) counts as 1 or 0.
+    if let Value::Bool(b) = interpolated {
+        return Ok(i64::from(b));
+    }
     let mut expr = value_to_string(&interpolated);
 
     if expr
@@ -98,10 +102,52 @@ fn eval_function(
             let v = eval_arithmetic(inner)?;
             Ok(if v > 0.0 { 1.0 } else if v < 0.0 { -1.0 } else { 0.0 })
         }
+        "abs" => Ok(eval_arithmetic(inner)?.abs()),
+        "floor" => Ok(eval_arithmetic(inner)?.floor()),
+        "ceil" => Ok(eval_arithmetic(inner)?.ceil()),
+        "clamp" => {
+            let args = split_args(inner)?;
+            let [value, min, max] = args[..] else {
+                return Err(anyhow!("clamp() expects 3 arguments, got {}", args.len()));
+            };
+            if min > max {
+                return Err(anyhow!("clamp() min {min} is greater than max {max}"));
+            }
+            Ok(value.clamp(min, max))
+        }
+        "log2" | "log10" => {
+            let v = eval_arithmetic(inner)?;
+            if v <= 0.0 {
+                return Err(anyhow!("{name}() of non-positive value {v}"));
+            }
+            Ok(if name == "log2" { v.log2() } else { v.log10() })
+        }
+        "pow" => {
+            let args = split_args(inner)?;
+            let [base, exp] = args[..] else {
+                return Err(anyhow!("pow() expects 2 arguments, got {}", args.len()));
+            };
+            Ok(base.powf(exp))
+        }
+        "sqrt" => {
+            let v = eval_arithmetic(inner)?;
+            if v < 0.0 {
+                return Err(anyhow!("sqrt() of negative value {v}"));
+            }
+            Ok(v.sqrt())
+        }
         _ => Err(anyhow!("Unknown math function '{name}'")),
     }
 }
 
+fn split_args(inner: &str) -> Result<Vec<f64>> {
+    inner
+        .split(',')
+        .filter(|part| !part.trim().is_empty())
+        .map(eval_arithmetic)
+        .collect()
+}
+
 fn eval_min_max(
     inserts: &Map<String, Value>,
     inner: &str,
@@ -110,13 +156,7 @@ fn eval_min_max(
 ) -> Result<f64> {
     let numeric = inner.chars().all(|c| " .0123456789+-*/%^,".contains(c));
     if numeric {
-        let mut nums = Vec::new();
-        for part in inner.split(',') {
-            if part.trim().is_empty() {
-                continue;
-            }
-            nums.push(eval_arithmetic(part)?);
-        }
+        let nums = split_args(inner)?;
         if nums.is_empty() {
             return Err(anyhow!("min/max requires at least one value"));
         }
@@ -270,3 +310,68 @@ fn eval_rpn(tokens: &[Token]) -> Result<f64> {
     }
     Ok(stack[0])
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn eval(input: &str) -> Result<i64> {
+        let ctx = ProgramLoadContext::new(PathBuf::from("test.json5"), Vec::new()).unwrap();
+        eval_math(&Map::new(), input, &ctx)
+    }
+
+    #[test]
+    fn numeric_functions_on_integers() {
+        assert_eq!(eval("abs(-4)").unwrap(), 4);
+        assert_eq!(eval("floor(7)").unwrap(), 7);
+        assert_eq!(eval("ceil(-3)").unwrap(), -3);
+        assert_eq!(eval("sqrt(16)").unwrap(), 4);
+    }
+
+    #[test]
+    fn numeric_functions_on_fractions() {
+        assert_eq!(eval("abs(-3.5)*2").unwrap(), 7);
+        assert_eq!(eval("floor(2.7)").unwrap(), 2);
+        assert_eq!(eval("floor(-2.2)").unwrap(), -3);
+        assert_eq!(eval("ceil(2.2)").unwrap(), 3);
+        assert_eq!(eval("sqrt(6.25)*2").unwrap(), 5);
+    }
+
+    #[test]
+    fn sqrt_of_negative_is_an_error() {
+        let err = eval("sqrt(-4)").unwrap_err();
+        assert!(err.to_string().contains("negative"), "{err}");
+        assert!(eval("sqrt(1-5)").is_err());
+    }
+
+    #[test]
+    fn clamp_branches() {
+        assert_eq!(eval("clamp(-5, 0, 10)").unwrap(), 0);
+        assert_eq!(eval("clamp(15, 0, 10)").unwrap(), 10);
+        assert_eq!(eval("clamp(7, 0, 10)").unwrap(), 7);
+    }
+
+    #[test]
+    fn clamp_edge_cases() {
+        assert_eq!(eval("clamp(0, 0, 10)").unwrap(), 0);
+        assert_eq!(eval("clamp(10, 0, 10)").unwrap(), 10);
+        assert_eq!(eval("clamp(3, 4, 4)").unwrap(), 4);
+        assert_eq!(eval("clamp(2*6, 1+1, 5*2)").unwrap(), 10);
+    }
+
+    #[test]
+    fn clamp_errors() {
+        assert!(eval("clamp(5, 10, 0)").is_err());
+        assert!(eval("clamp(5, 10)").is_err());
+        assert!(eval("clamp(5, 0, 10, 20)").is_err());
+    }
+
+    #[test]
+    fn bool_insert_counts_as_one_or_zero() {
+        let ctx = ProgramLoadContext::new(PathBuf::from("test.json5"), Vec::new()).unwrap();
+        let inserts = serde_json::json!({"yes": true, "no": false}).as_object().cloned().unwrap();
+        assert_eq!(eval_math(&inserts, "{yes}", &ctx).unwrap(), 1);
+        assert_eq!(eval_math(&inserts, "{no}", &ctx).unwrap(), 0);
+    }
+}
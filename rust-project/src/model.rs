@@ -18,27 +18,26 @@ pub struct Program {
 pub struct ProgramLoadContext {
     pub program_path: PathBuf,
     pub program_dir: PathBuf,
-    pub inserts_dir: Option<PathBuf>,
+    /// Directories searched in order for inserts missing from the state.
+    pub inserts_dirs: Vec<PathBuf>,
 }
 
 impl ProgramLoadContext {
-    pub fn new(program_path: PathBuf, inserts_dir: Option<PathBuf>) -> Result<Self> {
+    pub fn new(program_path: PathBuf, inserts_dirs: Vec<PathBuf>) -> Result<Self> {
         let program_dir = program_path
             .parent()
             .ok_or_else(|| anyhow!("Program path has no parent directory"))?
             .to_path_buf();
-        if let Some(dir) = inserts_dir.as_ref() {
-            if !dir.is_dir() {
-                return Err(anyhow!(
-                    "--inserts-dir must be an existing directory, got '{}'",
-                    dir.display()
-                ));
-            }
-        };
+        if let Some(dir) = inserts_dirs.iter().find(|dir| !dir.is_dir()) {
+            return Err(anyhow!(
+                "--inserts-dir must be an existing directory, got '{}'",
+                dir.display()
+            ));
+        }
         Ok(Self {
             program_path,
             program_dir,
-            inserts_dir,
+            inserts_dirs,
         })
     }
 }
@@ -1,8 +1,10 @@
-use crate::chat::{run_chat, ChatArgs, ChatResult};
+use crate::chat::{run_chat, ChatArgs, ChatResult, ToolExecutor};
+use crate::codec::{decode_base64, encode_base64};
 use async_recursion::async_recursion;
 use crate::interp::{
-    delete_interpdata, get_interpdata, get_simple_insertkey, interpolate_inserts, recursive_interpolate,
-    recursive_unescape, set_interpdata, value_to_string, ESCAPE, INSERT_START, INSERT_STOP,
+    delete_interpdata, get_interpdata, get_simple_insertkey, interpolate_inserts, recursive_escape,
+    recursive_interpolate, recursive_unescape, set_interpdata, value_at_path, value_to_string, ESCAPE, INSERT_START,
+    INSERT_STOP,
 };
 use crate::math::eval_math;
 use crate::model::{Program, ProgramLoadContext, Task};
@@ -10,8 +12,12 @@ use crate::save::splice_key_into_json5;
 use crate::audio_web;
 use crate::ui::{start_ui, UiCommandHandle, UiEvent};
 use anyhow::{anyhow, Result};
-use chrono::{SecondsFormat, Utc};
+use chrono::{Local, SecondsFormat, Utc};
 use rand::random;
+use rand::rngs::SmallRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use sha2::Digest;
 use serde_json::{json, Map, Value};
 use std::collections::HashMap;
 use std::fs;
@@ -23,6 +29,7 @@ use std::sync::Mutex as StdMutex;
 use tokio::sync::Mutex;
 use tokio::time::{sleep, Duration};
 use futures::stream::{FuturesUnordered, StreamExt};
+use futures::FutureExt;
 use std::future::Future;
 use tokio_util::sync::CancellationToken;
 
@@ -35,6 +42,9 @@ pub struct RuntimeOptions {
     pub history_path: Option<PathBuf>,
     pub audio_web: bool,
     pub audio_port: u16,
+    pub output_buffer_lines: Option<usize>,
+    /// Run control flow normally but skip side effects (file writes, network, speech, prompts).
+    pub dry_run: bool,
 }
 
 #[derive(Clone)]
@@ -85,6 +95,7 @@ impl Logger {
 
 const PREVIEW_SHORT: usize = 80;
 const PREVIEW_LONG: usize = 140;
+pub(crate) const COERCE_TYPES: &[&str] = &["string", "number", "integer", "boolean", "null"];
 
 fn format_pretty_event(event: &str, fields: &Map<String, Value>, ts: &str) -> Option<String> {
     let mut lines = Vec::new();
@@ -95,8 +106,9 @@ fn format_pretty_event(event: &str, fields: &Map<String, Value>, ts: &str) -> Op
             let agent_mode = map_bool(fields, "agent_mode").unwrap_or(false);
             let audio_web = map_bool(fields, "audio_web").unwrap_or(false);
             let audio_port = map_i64(fields, "audio_port").unwrap_or(0);
+            let dry_run = map_bool(fields, "dry_run").unwrap_or(false);
             lines.push(format!(
-                "[{ts}] Program start: {program} (order_len={order_len}, agent_mode={agent_mode}, audio_web={audio_web}, audio_port={audio_port})"
+                "[{ts}] Program start: {program} (order_len={order_len}, agent_mode={agent_mode}, audio_web={audio_web}, audio_port={audio_port}, dry_run={dry_run})"
             ));
         }
         "program_complete" => {
@@ -133,16 +145,28 @@ fn format_pretty_event(event: &str, fields: &Map<String, Value>, ts: &str) -> Op
                 lines.push(format!("[{ts}] User entered {value} -> {output_name}."));
             }
         }
+        "user_confirm" => {
+            let output_name = map_string(fields, "output_name").unwrap_or_default();
+            let question = map_string(fields, "question").unwrap_or_default();
+            let answer = if map_i64(fields, "result").unwrap_or(0) == 1 { "yes" } else { "no" };
+            lines.push(format!(
+                "[{ts}] User answered {answer} to {} -> {output_name}.",
+                preview_text(&question, PREVIEW_SHORT)
+            ));
+        }
         "user_choice" => {
             let output_name = map_string(fields, "output_name").unwrap_or_default();
             let choice = map_value(fields, "choice")
                 .map(|v| preview_value(v, PREVIEW_SHORT))
                 .unwrap_or_else(|| "\"\"".to_string());
-            let index = map_i64(fields, "index").unwrap_or(-1);
+            let position = match map_value(fields, "indices") {
+                Some(indices) => format!("indices {}", preview_value(indices, PREVIEW_SHORT)),
+                None => format!("index {}", map_i64(fields, "index").unwrap_or(-1)),
+            };
             if output_name.is_empty() {
-                lines.push(format!("[{ts}] User selected {choice} (index {index})."));
+                lines.push(format!("[{ts}] User selected {choice} ({position})."));
             } else {
-                lines.push(format!("[{ts}] User selected {choice} (index {index}) -> {output_name}."));
+                lines.push(format!("[{ts}] User selected {choice} ({position}) -> {output_name}."));
             }
         }
         "random_choice" => {
@@ -157,6 +181,95 @@ fn format_pretty_event(event: &str, fields: &Map<String, Value>, ts: &str) -> Op
                 lines.push(format!("[{ts}] Random choice {choice} (index {index}) -> {output_name}."));
             }
         }
+        "random_int" => {
+            let output_name = map_string(fields, "output_name").unwrap_or_default();
+            let min = map_i64(fields, "min").unwrap_or(0);
+            let max = map_i64(fields, "max").unwrap_or(0);
+            let value = map_i64(fields, "value").unwrap_or(0);
+            lines.push(format!("[{ts}] Random int {value} (range {min}..={max}) -> {output_name}."));
+        }
+        "list_sort" => {
+            let output_name = map_string(fields, "output_name").unwrap_or_default();
+            let len = map_i64(fields, "len").unwrap_or(0);
+            let order = map_string(fields, "order").unwrap_or_default();
+            lines.push(format!("[{ts}] Sorted {len} items ({order}) -> {output_name}."));
+        }
+        "list_reverse" => {
+            let output_name = map_string(fields, "output_name").unwrap_or_default();
+            let len = map_i64(fields, "len").unwrap_or(0);
+            lines.push(format!("[{ts}] Reversed {len} items -> {output_name}."));
+        }
+        "string_split" => {
+            let output_name = map_string(fields, "output_name").unwrap_or_default();
+            let text_len = map_i64(fields, "text_len").unwrap_or(0);
+            let len = map_i64(fields, "len").unwrap_or(0);
+            lines.push(format!("[{ts}] Split text of {text_len} chars into {len} items -> {output_name}."));
+        }
+        "string_replace" => {
+            let output_name = map_string(fields, "output_name").unwrap_or_default();
+            let replaced = map_i64(fields, "replaced").unwrap_or(0);
+            lines.push(format!("[{ts}] Replaced {replaced} occurrences -> {output_name}."));
+        }
+        "string_trim" => {
+            let output_name = map_string(fields, "output_name").unwrap_or_default();
+            let original_len = map_i64(fields, "original_len").unwrap_or(0);
+            let trimmed_len = map_i64(fields, "trimmed_len").unwrap_or(0);
+            lines.push(format!("[{ts}] Trimmed {original_len} -> {trimmed_len} chars -> {output_name}."));
+        }
+        "list_shuffle" => {
+            let output_name = map_string(fields, "output_name").unwrap_or_default();
+            let len = map_i64(fields, "len").unwrap_or(0);
+            match map_i64(fields, "seed") {
+                Some(seed) => lines.push(format!(
+                    "[{ts}] Shuffled {len} items with seed {seed} -> {output_name}."
+                )),
+                None => lines.push(format!("[{ts}] Shuffled {len} items -> {output_name}.")),
+            }
+        }
+        "try_error" => {
+            let error = map_string(fields, "error").unwrap_or_default();
+            lines.push(format!("[{ts}] try caught error, running on_error: {error}"));
+        }
+        "assert_fail" => {
+            let condition = map_string(fields, "condition").unwrap_or_default();
+            let message = map_string(fields, "message").unwrap_or_default();
+            let level = map_string(fields, "level").unwrap_or_default();
+            lines.push(format!("[{ts}] Assertion failed ({level}): {condition}: {message}"));
+        }
+        "benchmark" => {
+            let label = map_string(fields, "label").unwrap_or_default();
+            let cmd = map_string(fields, "cmd").unwrap_or_default();
+            let elapsed_ms = map_i64(fields, "elapsed_ms").unwrap_or(0);
+            if label.is_empty() {
+                lines.push(format!("[{ts}] benchmark {cmd}: {elapsed_ms} ms."));
+            } else {
+                lines.push(format!("[{ts}] benchmark {label} ({cmd}): {elapsed_ms} ms."));
+            }
+        }
+        "retry_attempt" => {
+            let attempt = map_i64(fields, "attempt").unwrap_or(0);
+            let remaining = map_i64(fields, "remaining").unwrap_or(0);
+            let error = map_string(fields, "error").unwrap_or_default();
+            lines.push(format!(
+                "[{ts}] Attempt {attempt} failed ({remaining} remaining): {error}"
+            ));
+        }
+        "timeout" => {
+            let seconds = map_value(fields, "seconds").map(value_to_string).unwrap_or_default();
+            lines.push(format!("[{ts}] Task timed out after {seconds} seconds."));
+        }
+        "if_branch" => {
+            let condition = fields
+                .get("condition")
+                .map(|v| preview_value(v, PREVIEW_SHORT))
+                .unwrap_or_default();
+            let value = fields
+                .get("value")
+                .map(|v| preview_value(v, PREVIEW_SHORT))
+                .unwrap_or_default();
+            let branch = map_string(fields, "branch").unwrap_or_default();
+            lines.push(format!("[{ts}] if {condition} evaluated to {value} -> {branch}."));
+        }
         "goto" => {
             let target = map_string(fields, "target").unwrap_or_default();
             lines.push(format!("[{ts}] goto -> {target}."));
@@ -175,6 +288,16 @@ fn format_pretty_event(event: &str, fields: &Map<String, Value>, ts: &str) -> Op
                 ));
             }
         }
+        "switch" => {
+            let value = map_string(fields, "value").unwrap_or_default();
+            match fields.get("case").and_then(Value::as_str) {
+                Some(case) => lines.push(format!("[{ts}] switch value is {value}, matched case '{case}'.")),
+                None if map_bool(fields, "has_default").unwrap_or(false) => {
+                    lines.push(format!("[{ts}] switch value is {value}, no case matched; using default."))
+                }
+                None => lines.push(format!("[{ts}] switch value is {value}, no case matched.")),
+            }
+        }
         "replace_map" => {
             let output_name = map_string(fields, "output_name").unwrap_or_default();
             let repeat = map_bool(fields, "repeat_until_done").unwrap_or(false);
@@ -190,6 +313,15 @@ fn format_pretty_event(event: &str, fields: &Map<String, Value>, ts: &str) -> Op
                 ));
             }
         }
+        "repeat_iteration" => {
+            let iteration = map_i64(fields, "iteration").unwrap_or(0);
+            let count = map_i64(fields, "count").unwrap_or(0);
+            lines.push(format!("[{ts}] Repeat starting iteration {iteration} of {count}."));
+        }
+        "while_iteration" => {
+            let iteration = map_i64(fields, "iteration").unwrap_or(0);
+            lines.push(format!("[{ts}] While loop starting iteration {iteration}."));
+        }
         "for_iteration" => {
             let iteration = map_i64(fields, "iteration").unwrap_or(0);
             lines.push(format!("[{ts}] For loop starting iteration {iteration}."));
@@ -222,11 +354,61 @@ fn format_pretty_event(event: &str, fields: &Map<String, Value>, ts: &str) -> Op
                 lines.push(format!("[{ts}] Math: {input} => {result}."));
             }
         }
+        "json_parse" => {
+            let output_name = map_string(fields, "output_name").unwrap_or_default();
+            let text_len = map_i64(fields, "text_len").unwrap_or(0);
+            let ok = map_bool(fields, "ok").unwrap_or(false);
+            let result = if ok { "parsed" } else { "could not be parsed" };
+            lines.push(format!("[{ts}] json_parse -> {output_name}: {text_len} characters {result}."));
+        }
+        "error" => {
+            let message = map_string(fields, "message").unwrap_or_default();
+            let code = map_i64(fields, "code").unwrap_or(1);
+            lines.push(format!("[{ts}] error (code {code}): {message}"));
+        }
+        "hash" => {
+            let output_name = map_string(fields, "output_name").unwrap_or_default();
+            let algorithm = map_string(fields, "algorithm").unwrap_or_default();
+            let output_len = map_i64(fields, "output_len").unwrap_or(0);
+            lines.push(format!("[{ts}] hash ({algorithm}) -> {output_name}: {output_len} hex characters."));
+        }
+        "env_get" => {
+            let name = map_string(fields, "name").unwrap_or_default();
+            let output_name = map_string(fields, "output_name").unwrap_or_default();
+            lines.push(format!("[{ts}] env_get: {name} -> {output_name}."));
+        }
+        "read" => {
+            let path = map_string(fields, "path").unwrap_or_default();
+            let bytes = map_i64(fields, "bytes").unwrap_or(0);
+            lines.push(format!("[{ts}] read: '{path}' ({bytes} bytes)."));
+        }
+        "http_get" => {
+            let url = map_string(fields, "url").unwrap_or_default();
+            let status = map_i64(fields, "status").unwrap_or(0);
+            let bytes = map_i64(fields, "bytes").unwrap_or(0);
+            lines.push(format!("[{ts}] http_get: '{url}' -> {status} ({bytes} bytes)."));
+        }
         "write" => {
             let path = map_string(fields, "path").unwrap_or_default();
             let bytes = map_i64(fields, "bytes").unwrap_or(0);
             lines.push(format!("[{ts}] write: '{path}' ({bytes} bytes)."));
         }
+        "merge_objects" => {
+            let output_name = map_string(fields, "output_name").unwrap_or_default();
+            let sources = map_i64(fields, "sources").unwrap_or(0);
+            let keys = map_i64(fields, "keys").unwrap_or(0);
+            lines.push(format!("[{ts}] merge_objects: {sources} objects -> {output_name} ({keys} keys)."));
+        }
+        "parallel_limit_start" => {
+            let tasks = map_i64(fields, "tasks").unwrap_or(0);
+            let max_concurrent = map_i64(fields, "max_concurrent").unwrap_or(0);
+            lines.push(format!("[{ts}] parallel_limit: {tasks} tasks, at most {max_concurrent} at a time."));
+        }
+        "dry_run_skip" => {
+            let cmd = map_string(fields, "cmd").unwrap_or_default();
+            let target = map_string(fields, "target").unwrap_or_default();
+            lines.push(format!("[{ts}] Dry run: skipped {cmd} '{target}'."));
+        }
         "speak" => {
             let voice_path = map_string(fields, "voice_path").unwrap_or_default();
             let text_len = map_i64(fields, "text_len").unwrap_or(0);
@@ -249,9 +431,18 @@ fn format_pretty_event(event: &str, fields: &Map<String, Value>, ts: &str) -> Op
             let output_name = map_string(fields, "output_name").unwrap_or_default();
             let outputs = map_i64(fields, "outputs").unwrap_or(0);
             let visual_len = map_i64(fields, "visual_len").unwrap_or(0);
-            lines.push(format!(
-                "[{ts}] Chat done: {output_name} (outputs={outputs}, visual_len={visual_len})."
-            ));
+            let total_tokens = fields
+                .get("usage")
+                .and_then(|u| u.get("total_tokens"))
+                .and_then(Value::as_i64);
+            match total_tokens {
+                Some(tokens) => lines.push(format!(
+                    "[{ts}] Chat done: {output_name} (outputs={outputs}, visual_len={visual_len}, tokens={tokens})."
+                )),
+                None => lines.push(format!(
+                    "[{ts}] Chat done: {output_name} (outputs={outputs}, visual_len={visual_len})."
+                )),
+            }
             let messages = fields.get("messages");
             let assistant = fields.get("assistant_raw");
             if messages.is_some() || assistant.is_some() {
@@ -442,6 +633,7 @@ pub async fn run_program(
             "agent_mode": options.agent_mode,
             "audio_web": options.audio_web,
             "audio_port": options.audio_port,
+            "dry_run": options.dry_run,
         }),
     );
 
@@ -464,7 +656,7 @@ pub async fn run_program(
         let (ui_cmd, mut ui_events, ui_join) = if options.agent_mode {
         (None, None, None)
     } else {
-        let (cmd, events, join) = start_ui(options.history_path.clone());
+        let (cmd, events, join) = start_ui(options.history_path.clone(), options.output_buffer_lines);
         (Some(cmd), Some(events), Some(join))
     };
 
@@ -537,6 +729,7 @@ pub async fn run_program(
                 token.child_token(),
                 "root".to_string(),
                 logger.clone(),
+                options.dry_run,
             );
             let mut exec_fut = Box::pin(exec_fut);
 
@@ -554,6 +747,9 @@ pub async fn run_program(
                                     state.lock().await.set_i64("order_index", (idx + 2) as i64);
                                     break;
                                 }
+                                Ok(TaskOutcome::Break) | Ok(TaskOutcome::Continue) => {
+                                    return Err(anyhow!("break/continue used outside of a loop"));
+                                }
                                 Err(e) => {
                                     if is_cancelled(&e) || token.is_cancelled() {
                                         let mut saw_event = false;
@@ -612,6 +808,9 @@ pub async fn run_program(
                         let idx = find_label_index(&program.order, &target)?;
                         state.lock().await.set_i64("order_index", (idx + 2) as i64);
                     }
+                    TaskOutcome::Break | TaskOutcome::Continue => {
+                        return Err(anyhow!("break/continue used outside of a loop"));
+                    }
                 }
             }
         }
@@ -646,10 +845,31 @@ pub async fn run_program(
     run_result
 }
 
+/// Raised by the `error` command. `code` becomes the process exit code.
+#[derive(Debug, thiserror::Error)]
+#[error("{message}")]
+pub struct ProgramError {
+    pub message: String,
+    pub code: i32,
+}
+
 #[derive(Debug)]
 enum TaskOutcome {
     None,
     Goto(String),
+    Break,
+    Continue,
+}
+
+/// The named task run per item by `map`, `filter_list` and `reduce` must finish normally:
+/// a goto, break or continue cannot jump out of it.
+fn reject_escaping_outcome(cmd: &str, outcome: &TaskOutcome) -> Result<()> {
+    match outcome {
+        TaskOutcome::None => Ok(()),
+        TaskOutcome::Goto(target) => Err(anyhow!("goto '{target}' cannot leave a {cmd} task")),
+        TaskOutcome::Break => Err(anyhow!("break cannot leave a {cmd} task")),
+        TaskOutcome::Continue => Err(anyhow!("continue cannot leave a {cmd} task")),
+    }
 }
 
 fn task_label(task: &Task, fallback_index: usize) -> String {
@@ -674,6 +894,7 @@ async fn execute_task(
     token: CancellationToken,
     runtime_label: String,
     logger: Arc<Logger>,
+    dry_run: bool,
 ) -> Result<TaskOutcome> {
     if token.is_cancelled() {
         return Err(anyhow!("cancelled"));
@@ -736,6 +957,39 @@ async fn execute_task(
             }
             with_inserts(state, |ins| set_interpdata(ins, &output_name, Value::Array(out))).await;
         }
+        "zip_lists" => {
+            let lists = task
+                .get("lists")
+                .and_then(Value::as_object)
+                .ok_or_else(|| anyhow!("zip_lists.lists must be an object"))?;
+            let output_name = as_string(&task, "output_name")?;
+            let strict = task.get("strict").and_then(Value::as_bool).unwrap_or(false);
+            let mut columns = Vec::new();
+            for (key, list) in lists {
+                let list = list
+                    .as_array()
+                    .ok_or_else(|| anyhow!("zip_lists.lists.{key} must be a list"))?;
+                columns.push((key, list));
+            }
+            let len = columns.iter().map(|(_, list)| list.len()).max().unwrap_or(0);
+            if let Some((key, list)) = columns.iter().find(|(_, list)| strict && list.len() != len) {
+                return Err(anyhow!(
+                    "zip_lists.lists.{key} has {} items, expected {len} (strict)",
+                    list.len()
+                ));
+            }
+            let zipped = (0..len)
+                .map(|i| {
+                    Value::Object(
+                        columns
+                            .iter()
+                            .map(|(key, list)| ((*key).clone(), list.get(i).cloned().unwrap_or(Value::Null)))
+                            .collect(),
+                    )
+                })
+                .collect::<Vec<_>>();
+            with_inserts(state, |ins| set_interpdata(ins, &output_name, Value::Array(zipped))).await;
+        }
         "list_append" => {
             let list = as_array(&task, "list")?;
             let item = task.get("item").cloned().unwrap_or(Value::Null);
@@ -786,120 +1040,981 @@ async fn execute_task(
             let output_name = as_string(&task, "output_name")?;
             with_inserts(state, |ins| set_interpdata(ins, &output_name, Value::Array(slice))).await;
         }
-        "user_choice" => {
-            let list = as_array(&task, "list")?;
-            let description = as_string(&task, "description")?;
+        "list_sort" => {
+            let mut list = as_array(&task, "list")?;
+            let order = task.get("order").and_then(Value::as_str).unwrap_or("asc");
             let output_name = as_string(&task, "output_name")?;
-            if list.is_empty() {
-                let _ = await_with_cancel(
-                    &token,
-                    &io,
-                    io.select_index(Vec::new(), Some(description), true),
-                )
-                .await?;
-                with_inserts(state, |ins| set_interpdata(ins, &output_name, Value::Null)).await;
-            } else {
-                let options = list.iter().map(value_to_string).collect::<Vec<_>>();
-                let choice_index = await_with_cancel(
-                    &token,
-                    &io,
-                    io.select_index(options, Some(description), true),
-                )
-                .await?;
-                let choice = list
-                    .get(choice_index)
-                    .ok_or_else(|| anyhow!("Choice index out of bounds"))?
-                    .clone();
-                logger.log(
-                    "user_choice",
-                    json!({
-                        "output_name": output_name.clone(),
-                        "index": choice_index,
-                        "choice": value_to_string(&choice),
-                    }),
-                );
-                with_inserts(state, |ins| set_interpdata(ins, &output_name, choice)).await;
+            if order != "asc" && order != "desc" {
+                return Err(anyhow!("list_sort.order must be 'asc' or 'desc', got '{order}'"));
+            }
+            sort_values(&mut list);
+            if order == "desc" {
+                list.reverse();
             }
+            logger.log(
+                "list_sort",
+                json!({
+                    "output_name": output_name.clone(),
+                    "len": list.len(),
+                    "order": order,
+                }),
+            );
+            with_inserts(state, |ins| set_interpdata(ins, &output_name, Value::Array(list))).await;
         }
-        "user_input" => {
-            let prompt = as_string(&task, "prompt")?;
+        "list_reverse" => {
+            let mut list = as_array(&task, "list")?;
             let output_name = as_string(&task, "output_name")?;
-            let input = await_with_cancel(
-                &token,
-                &io,
-                io.user_input(prompt, String::new(), true),
-            )
-            .await?;
-            let escaped = input
-                .replace(INSERT_START, &format!("{ESCAPE}{INSERT_START}"))
-                .replace(INSERT_STOP, &format!("{ESCAPE}{INSERT_STOP}"));
+            list.reverse();
             logger.log(
-                "user_input",
+                "list_reverse",
+                json!({
+                    "output_name": output_name.clone(),
+                    "len": list.len(),
+                }),
+            );
+            with_inserts(state, |ins| set_interpdata(ins, &output_name, Value::Array(list))).await;
+        }
+        "list_unique" | "list_dedupe" => {
+            let list = as_array(&task, "list")?;
+            let output_name = as_string(&task, "output_name")?;
+            let unique = dedupe_values(list);
+            with_inserts(state, |ins| set_interpdata(ins, &output_name, Value::Array(unique))).await;
+        }
+        "list_shuffle" => {
+            let mut list = as_array(&task, "list")?;
+            let output_name = as_string(&task, "output_name")?;
+            let seed = match task.get("seed") {
+                None | Some(Value::Null) => None,
+                Some(v) => Some(
+                    eval_math_index(v, &inserts_snapshot, &ctx)
+                        .map_err(|_| anyhow!("list_shuffle.seed must be an int or math expression"))?,
+                ),
+            };
+            match seed {
+                Some(seed) => list.shuffle(&mut SmallRng::seed_from_u64(seed as u64)),
+                None => list.shuffle(&mut rand::thread_rng()),
+            }
+            logger.log(
+                "list_shuffle",
                 json!({
                     "output_name": output_name.clone(),
-                    "value": input,
+                    "len": list.len(),
+                    "seed": seed,
                 }),
             );
+            with_inserts(state, |ins| set_interpdata(ins, &output_name, Value::Array(list))).await;
+        }
+        "list_find" => {
+            let list = as_array(&task, "list")?;
+            let pattern = as_string(&task, "pattern")?;
+            let output_name = as_string(&task, "output_name")?;
+            let index_name = task.get("index_name").and_then(Value::as_str).map(|s| s.to_string());
+            let found = list
+                .iter()
+                .position(|v| wildcard_match(&pattern, &value_to_string(v), false));
+            let item = found.map(|i| list[i].clone()).unwrap_or(Value::Null);
+            let index = found.map(|i| i as i64 + 1).unwrap_or(0);
             with_inserts(state, |ins| {
-                set_interpdata(ins, &output_name, Value::String(escaped))
+                set_interpdata(ins, &output_name, item);
+                if let Some(index_name) = index_name {
+                    set_interpdata(ins, &index_name, Value::Number(index.into()));
+                }
             })
             .await;
         }
-        "await_insert" => {
-            let name = as_string(&task, "name")?;
-            loop {
-                if token.is_cancelled() {
-                    return Err(anyhow!("cancelled"));
-                }
-                if state.lock().await.inserts().contains_key(&name) {
-                    break;
-                }
-                sleep(Duration::from_millis(50)).await;
-            }
+        "list_contains" => {
+            let list = as_array(&task, "list")?;
+            let item = task.get("item").cloned().unwrap_or(Value::Null);
+            let output_name = as_string(&task, "output_name")?;
+            let contains = contains_flag(&list, &item);
+            with_inserts(state, |ins| set_interpdata(ins, &output_name, Value::Number(contains.into()))).await;
         }
-        "run_task" => {
-            let name = as_string(&task, "task_name")?;
-            let subtask = named_tasks
-                .get(&name)
-                .cloned()
-                .ok_or_else(|| anyhow!("Unknown task '{name}'"))?;
-            return execute_task(
-                state,
-                subtask,
-                completion_args.clone(),
-                named_tasks.clone(),
-                ctx.clone(),
-                io.clone(),
-                token,
-                format!("{runtime_label}/{name}"),
-                logger.clone(),
-            )
+        "list_filter" => {
+            let list = as_array(&task, "list")?;
+            let pattern = as_string(&task, "pattern")?;
+            let output_name = as_string(&task, "output_name")?;
+            let negate = task.get("negate").and_then(Value::as_bool).unwrap_or(false);
+            let filtered = list
+                .into_iter()
+                .filter(|v| wildcard_match(&pattern, &value_to_string(v), false) != negate)
+                .collect::<Vec<_>>();
+            with_inserts(state, |ins| set_interpdata(ins, &output_name, Value::Array(filtered))).await;
+        }
+        "list_map" => {
+            let list = recursive_interpolate(
+                &inserts_snapshot,
+                task.get("list").cloned().unwrap_or(Value::Null),
+                &ctx,
+            )?;
+            let list = list
+                .as_array()
+                .ok_or_else(|| anyhow!("Field 'list' must be an array"))?
+                .clone();
+            let output_name = as_string(&task, "output_name")?;
+            let output_name = value_to_string(&interpolate_inserts(&inserts_snapshot, &output_name, &ctx)?);
+            let maps = task
+                .get("wildcard_maps")
+                .and_then(Value::as_array)
+                .ok_or_else(|| anyhow!("list_map.wildcard_maps must be array"))?
+                .clone();
+            let repeat_until_done = task
+                .get("repeat_until_done")
+                .and_then(Value::as_bool)
+                .unwrap_or(false);
+            let case_insensitive = task
+                .get("case_insensitive")
+                .and_then(Value::as_bool)
+                .unwrap_or(false);
+            let mapped = list
+                .into_iter()
+                .map(|item| replace_map(item, &maps, &inserts_snapshot, &ctx, repeat_until_done, case_insensitive))
+                .collect::<Result<Vec<_>>>()?;
+            with_inserts(state, |ins| set_interpdata(ins, &output_name, Value::Array(mapped))).await;
+        }
+        "list_pop" => {
+            let mut list = as_array(&task, "list")?;
+            let output_name = as_string(&task, "output_name")?;
+            let item_name = task.get("item_name").and_then(Value::as_str).map(|s| s.to_string());
+            let allow_empty = task.get("allow_empty").and_then(Value::as_bool).unwrap_or(false);
+            let item = match list.pop() {
+                Some(item) => item,
+                None if allow_empty => Value::Null,
+                None => return Err(anyhow!("list_pop list is empty")),
+            };
+            with_inserts(state, |ins| {
+                set_interpdata(ins, &output_name, Value::Array(list));
+                if let Some(item_name) = item_name {
+                    set_interpdata(ins, &item_name, item);
+                }
+            })
             .await;
         }
-        "parallel_wait" => {
-            let tasks = as_task_array(&task, "tasks")?;
-            let futures = tasks.into_iter().enumerate().map(|(index, t)| {
-                let child_label = format!("{}/{}", runtime_label, task_label(&t, index + 1));
-                execute_task(
-                    state.clone(),
-                    t,
-                    completion_args.clone(),
-                    named_tasks.clone(),
-                    ctx.clone(),
-                    io.clone(),
-                    token.child_token(),
-                    child_label,
-                    logger.clone(),
-                )
-            });
-            let results = futures::future::join_all(futures).await;
-            for res in results {
-                res?;
+        "list_push_front" => {
+            let list = as_array(&task, "list")?;
+            let item = task.get("item").cloned().unwrap_or(Value::Null);
+            let output_name = as_string(&task, "output_name")?;
+            let mut new_list = list.clone();
+            new_list.insert(0, item);
+            with_inserts(state, |ins| set_interpdata(ins, &output_name, Value::Array(new_list))).await;
+        }
+        "string_trim" => {
+            let text = as_string(&task, "text")?;
+            let output_name = as_string(&task, "output_name")?;
+            let mode = task
+                .get("mode")
+                .or_else(|| task.get("side"))
+                .and_then(Value::as_str)
+                .unwrap_or("both");
+            let chars: Option<Vec<char>> = task.get("chars").and_then(Value::as_str).map(|c| c.chars().collect());
+            let trimmed = trim_text(&text, mode, chars.as_deref())?;
+            logger.log(
+                "string_trim",
+                json!({
+                    "output_name": output_name.clone(),
+                    "original_len": text.chars().count(),
+                    "trimmed_len": trimmed.chars().count(),
+                }),
+            );
+            let trimmed = Value::String(trimmed.to_string());
+            with_inserts(state, |ins| set_interpdata(ins, &output_name, trimmed)).await;
+        }
+        "string_upper" | "string_lower" => {
+            let text = as_string(&task, "text")?;
+            let output_name = as_string(&task, "output_name")?;
+            let converted = convert_case(&text, cmd == "string_upper");
+            with_inserts(state, |ins| set_interpdata(ins, &output_name, Value::String(converted))).await;
+        }
+        "string_pad" => {
+            let text = as_string(&task, "text")?;
+            let align = as_string(&task, "align")?;
+            let output_name = as_string(&task, "output_name")?;
+            let width_val = task.get("width").cloned().unwrap_or(Value::Null);
+            let width = if let Some(expr) = width_val.as_str() {
+                eval_math(&inserts_snapshot, expr, &ctx)?
+            } else {
+                width_val.as_i64().ok_or_else(|| anyhow!("string_pad.width must be an int"))?
+            };
+            let pad_char = task.get("pad_char").and_then(Value::as_str).unwrap_or(" ");
+            let mut pad_chars = pad_char.chars();
+            let pad_char = match (pad_chars.next(), pad_chars.next()) {
+                (Some(c), None) => c,
+                _ => return Err(anyhow!("string_pad.pad_char must be a single character, got '{pad_char}'")),
+            };
+            let missing = (width.max(0) as usize).saturating_sub(text.chars().count());
+            let (left, right) = match align.as_str() {
+                "left" => (0, missing),
+                "right" => (missing, 0),
+                "center" => (missing / 2, missing - missing / 2),
+                _ => return Err(anyhow!("string_pad.align must be 'left', 'right' or 'center', got '{align}'")),
+            };
+            let padded = format!(
+                "{}{}{}",
+                pad_char.to_string().repeat(left),
+                text,
+                pad_char.to_string().repeat(right)
+            );
+            with_inserts(state, |ins| set_interpdata(ins, &output_name, Value::String(padded))).await;
+        }
+        "string_slice" => {
+            let text = as_string(&task, "text")?;
+            let from_val = task.get("from_index").cloned().unwrap_or(Value::Null);
+            let to_val = task.get("to_index").cloned().unwrap_or(Value::Null);
+            let from = eval_math_index(&from_val, &inserts_snapshot, &ctx)?;
+            let to = eval_math_index(&to_val, &inserts_snapshot, &ctx)?;
+            let output_name = as_string(&task, "output_name")?;
+            if from == 0 {
+                return Err(anyhow!("Lower slice index cannot be 0 (1-based)"));
             }
+            let chars = text.chars().collect::<Vec<_>>();
+            let sliced = if to == 0 {
+                String::new()
+            } else {
+                match slice_indices(from, to, chars.len()) {
+                    Ok((start, end)) if end >= start => chars[start..=end].iter().collect(),
+                    _ => String::new(),
+                }
+            };
+            with_inserts(state, |ins| set_interpdata(ins, &output_name, Value::String(sliced))).await;
         }
-        "parallel_race" => {
-            let tasks = as_task_array(&task, "tasks")?;
-            let group = token.child_token();
+        "regex_match" => {
+            let text = as_string(&task, "text")?;
+            let pattern = as_string(&task, "pattern")?;
+            let output_name = as_string(&task, "output_name")?;
+            let groups_name = task
+                .get("capture_groups")
+                .or_else(|| task.get("groups_name"))
+                .and_then(Value::as_str)
+                .map(|s| s.to_string());
+            let re = build_regex(&task, &pattern)?;
+            let captures = re.captures(&text);
+            let matched = captures.is_some();
+            let groups = captures
+                .map(|caps| {
+                    caps.iter()
+                        .skip(1)
+                        .map(|c| Value::String(c.map(|m| m.as_str().to_string()).unwrap_or_default()))
+                        .collect::<Vec<_>>()
+                })
+                .unwrap_or_default();
+            with_inserts(state, |ins| {
+                set_interpdata(ins, &output_name, Value::Bool(matched));
+                if let Some(groups_name) = groups_name {
+                    set_interpdata(ins, &groups_name, Value::Array(groups));
+                }
+            })
+            .await;
+        }
+        "regex_find_all" => {
+            let text = as_string(&task, "text")?;
+            let pattern = as_string(&task, "pattern")?;
+            let output_name = as_string(&task, "output_name")?;
+            let re = build_regex(&task, &pattern)?;
+            let capture_group = match task.get("capture_group") {
+                Some(v) => Some(eval_math_index(v, &inserts_snapshot, &ctx)?),
+                None => None,
+            };
+            if let Some(group) = capture_group.filter(|g| *g < 0 || *g as usize >= re.captures_len()) {
+                return Err(anyhow!(
+                    "regex_find_all.capture_group {group} does not exist (pattern has {} groups)",
+                    re.captures_len() - 1
+                ));
+            }
+            let matches = re
+                .captures_iter(&text)
+                .map(|caps| {
+                    if let Some(group) = capture_group {
+                        Value::String(caps.get(group as usize).map(|m| m.as_str().to_string()).unwrap_or_default())
+                    } else if caps.len() > 1 {
+                        Value::Array(
+                            caps.iter()
+                                .skip(1)
+                                .map(|c| Value::String(c.map(|m| m.as_str().to_string()).unwrap_or_default()))
+                                .collect(),
+                        )
+                    } else {
+                        Value::String(caps[0].to_string())
+                    }
+                })
+                .collect::<Vec<_>>();
+            with_inserts(state, |ins| set_interpdata(ins, &output_name, Value::Array(matches))).await;
+        }
+        "json_parse" => {
+            let text = as_string(&task, "text")?;
+            let output_name = as_string(&task, "output_name")?;
+            let format = task.get("format").and_then(Value::as_str).map(|s| s.to_string());
+            let on_error = task.get("on_error").and_then(Value::as_str).unwrap_or("").to_string();
+            let fallback = task.get("fallback").cloned();
+            let raw = match recursive_unescape(Value::String(text)) {
+                Value::String(s) => s,
+                _ => unreachable!(),
+            };
+            let parsed: Result<Value> = match format.as_deref() {
+                None => serde_json::from_str(&raw).or_else(|_| json5::from_str(&raw)).map_err(|e| anyhow!(e)),
+                Some("json") => serde_json::from_str(&raw).map_err(|e| anyhow!(e)),
+                Some("json5") => json5::from_str(&raw).map_err(|e| anyhow!(e)),
+                Some(other) => return Err(anyhow!("json_parse format must be 'json' or 'json5', got '{other}'")),
+            };
+            logger.log(
+                "json_parse",
+                json!({
+                    "output_name": output_name.clone(),
+                    "text_len": raw.chars().count(),
+                    "ok": parsed.is_ok(),
+                }),
+            );
+            let value = match parsed {
+                Ok(v) => recursive_escape(v),
+                Err(e) => match fallback {
+                    Some(v) => v,
+                    None if on_error == "null" => Value::Null,
+                    None => {
+                        return Err(anyhow!(
+                            "json_parse failed: {e} (input: {})",
+                            preview_text(&raw, PREVIEW_LONG)
+                        ))
+                    }
+                },
+            };
+            with_inserts(state, |ins| set_interpdata(ins, &output_name, value)).await;
+        }
+        "json_stringify" => {
+            let item = task.get("item").cloned().unwrap_or(Value::Null);
+            let output_name = as_string(&task, "output_name")?;
+            let pretty = task.get("pretty").and_then(Value::as_bool).unwrap_or(false);
+            let item = recursive_unescape(item);
+            let text = if pretty {
+                serde_json::to_string_pretty(&item)?
+            } else {
+                serde_json::to_string(&item)?
+            };
+            with_inserts(state, |ins| {
+                set_interpdata(ins, &output_name, recursive_escape(Value::String(text)))
+            })
+            .await;
+        }
+        "object_get" => {
+            let object = task.get("object").cloned().unwrap_or(Value::Null);
+            let path = as_string(&task, "path")?;
+            let output_name = as_string(&task, "output_name")?;
+            let item = match value_at_path(&object, &path) {
+                Some(v) => v.clone(),
+                None => task
+                    .get("default")
+                    .cloned()
+                    .ok_or_else(|| anyhow!("object_get path '{path}' not found"))?,
+            };
+            with_inserts(state, |ins| set_interpdata(ins, &output_name, item)).await;
+        }
+        "object_set" => {
+            let mut object = task.get("object").cloned().unwrap_or(Value::Null);
+            let path = as_string(&task, "path")?;
+            let item = task.get("item").cloned().unwrap_or(Value::Null);
+            let output_name = as_string(&task, "output_name")?;
+            object_path_set(&mut object, &path, item)?;
+            with_inserts(state, |ins| set_interpdata(ins, &output_name, object)).await;
+        }
+        "merge_objects" => {
+            let objects = as_array(&task, "objects")?;
+            let output_name = as_string(&task, "output_name")?;
+            let mode = task.get("mode").map(value_to_string).unwrap_or_else(|| "shallow".to_string());
+            let deep = match mode.as_str() {
+                "shallow" => false,
+                "deep" => true,
+                other => return Err(anyhow!("merge_objects.mode must be 'shallow' or 'deep', got '{other}'")),
+            };
+            let mut merged = Map::new();
+            for (i, object) in objects.iter().enumerate() {
+                let object = object.as_object().ok_or_else(|| {
+                    anyhow!("merge_objects.objects item {} is not an object: {}", i + 1, preview_value(object, PREVIEW_SHORT))
+                })?;
+                if deep {
+                    deep_merge(&mut merged, object.clone());
+                } else {
+                    merged.extend(object.clone());
+                }
+            }
+            logger.log(
+                "merge_objects",
+                json!({
+                    "output_name": output_name.clone(),
+                    "sources": objects.len(),
+                    "keys": merged.len(),
+                }),
+            );
+            with_inserts(state, |ins| set_interpdata(ins, &output_name, Value::Object(merged))).await;
+        }
+        "object_has_key" => {
+            let object = task.get("object").cloned().unwrap_or(Value::Null);
+            let key = as_string(&task, "key")?;
+            let output_name = as_string(&task, "output_name")?;
+            let found = i64::from(value_at_path(&object, &key).is_some());
+            with_inserts(state, |ins| set_interpdata(ins, &output_name, Value::Number(found.into()))).await;
+        }
+        "timestamp" => {
+            let format = task.get("format").map(value_to_string).unwrap_or_else(|| "unix".to_string());
+            let output_name = as_string(&task, "output_name")?;
+            let utc = task.get("utc").and_then(Value::as_bool).unwrap_or(false);
+            let value = if format == "unix" {
+                Value::Number(Utc::now().timestamp().into())
+            } else if format == "unix_ms" {
+                Value::Number(Utc::now().timestamp_millis().into())
+            } else {
+                use std::fmt::Write as _;
+                let mut text = String::new();
+                let written = if utc {
+                    write!(text, "{}", Utc::now().format(&format))
+                } else {
+                    write!(text, "{}", Local::now().format(&format))
+                };
+                written.map_err(|_| anyhow!("timestamp.format '{format}' is not a valid strftime format"))?;
+                recursive_escape(Value::String(text))
+            };
+            with_inserts(state, |ins| set_interpdata(ins, &output_name, value)).await;
+        }
+        "uuid" => {
+            let format = task.get("format").map(value_to_string).unwrap_or_else(|| "hyphenated".to_string());
+            let output_name = as_string(&task, "output_name")?;
+            let id = uuid::Uuid::new_v4();
+            let text = match format.as_str() {
+                "hyphenated" => id.hyphenated().to_string(),
+                "simple" => id.simple().to_string(),
+                other => return Err(anyhow!("uuid.format must be 'hyphenated' or 'simple', got '{other}'")),
+            };
+            with_inserts(state, |ins| set_interpdata(ins, &output_name, Value::String(text))).await;
+        }
+        "base64_encode" | "base64_decode" => {
+            let text = as_string(&task, "text")?;
+            let output_name = as_string(&task, "output_name")?;
+            let raw = match recursive_unescape(Value::String(text)) {
+                Value::String(s) => s,
+                _ => unreachable!(),
+            };
+            let converted = if cmd == "base64_encode" {
+                encode_base64(raw.as_bytes())
+            } else {
+                let encoding = task.get("encoding").map(value_to_string).unwrap_or_else(|| "utf8".to_string());
+                decode_base64(&raw, &encoding)?
+            };
+            with_inserts(state, |ins| {
+                set_interpdata(ins, &output_name, recursive_escape(Value::String(converted)))
+            })
+            .await;
+        }
+        "hash" => {
+            let text = as_string(&task, "text")?;
+            let output_name = as_string(&task, "output_name")?;
+            let algorithm = task.get("algorithm").map(value_to_string).unwrap_or_else(|| "sha256".to_string());
+            let raw = match recursive_unescape(Value::String(text)) {
+                Value::String(s) => s,
+                _ => unreachable!(),
+            };
+            let digest = match algorithm.as_str() {
+                "sha256" => format!("{:x}", sha2::Sha256::digest(raw.as_bytes())),
+                "sha1" => format!("{:x}", sha1::Sha1::digest(raw.as_bytes())),
+                "md5" => format!("{:x}", md5::Md5::digest(raw.as_bytes())),
+                other => return Err(anyhow!("hash.algorithm must be 'sha256', 'sha1' or 'md5', got '{other}'")),
+            };
+            logger.log(
+                "hash",
+                json!({
+                    "output_name": output_name.clone(),
+                    "algorithm": algorithm,
+                    "output_len": digest.len(),
+                }),
+            );
+            with_inserts(state, |ins| set_interpdata(ins, &output_name, Value::String(digest))).await;
+        }
+        "env_get" => {
+            let name = as_string(&task, "name")?;
+            let output_name = as_string(&task, "output_name")?;
+            let value = match (std::env::var(&name), task.get("fallback")) {
+                (Ok(v), _) => recursive_escape(Value::String(v)),
+                (Err(_), Some(fallback)) => fallback.clone(),
+                (Err(e), None) => return Err(anyhow!("env_get could not read '{name}': {e}")),
+            };
+            logger.log("env_get", json!({ "name": name.clone(), "output_name": output_name.clone() }));
+            with_inserts(state, |ins| set_interpdata(ins, &output_name, value)).await;
+        }
+        "error" => {
+            let message = as_string(&task, "message")?;
+            let code = match task.get("code") {
+                Some(v) => eval_math_index(v, &inserts_snapshot, &ctx)? as i32,
+                None => 1,
+            };
+            logger.log("error", json!({ "message": message.clone(), "code": code }));
+            return Err(ProgramError { message, code }.into());
+        }
+        "assert" => {
+            let condition = task.get("condition").cloned().unwrap_or(Value::Null);
+            let message = task
+                .get("message")
+                .map(value_to_string)
+                .unwrap_or_else(|| "condition is false".to_string());
+            let warn_only = task.get("warn_only").and_then(Value::as_bool).unwrap_or(false);
+            let default_level = if warn_only { "warn" } else { "error" };
+            let level = task.get("level").and_then(Value::as_str).unwrap_or(default_level).to_string();
+            let condition_text = value_to_string(&condition);
+            let holds = match &condition {
+                Value::String(s) => eval_math(&inserts_snapshot, s, &ctx)? != 0,
+                Value::Bool(b) => *b,
+                v => v.as_f64().ok_or_else(|| anyhow!("assert.condition must be a math expression"))? != 0.0,
+            };
+            if !holds {
+                logger.log(
+                    "assert_fail",
+                    json!({
+                        "condition": condition_text.clone(),
+                        "message": message.clone(),
+                        "level": level.clone(),
+                    }),
+                );
+                match level.as_str() {
+                    "error" => return Err(anyhow!("Assertion failed ({condition_text}): {message}")),
+                    "warn" => {
+                        let warning = format!("Warning: assertion failed ({condition_text}): {message}");
+                        // The TUI owns the terminal, so there the warning goes to the output pane.
+                        match &io {
+                            Io::Agent(_) => eprintln!("{warning}"),
+                            Io::Ui(_) => io.write(format!("\n({warning})\n")).await,
+                        }
+                    }
+                    other => return Err(anyhow!("assert.level must be 'error' or 'warn', got '{other}'")),
+                }
+            }
+        }
+        "type_of" => {
+            let item = task.get("item").cloned().unwrap_or(Value::Null);
+            let output_name = as_string(&task, "output_name")?;
+            let actual = type_name(&item);
+            if let Some(expected) = task.get("expected").and_then(Value::as_str).filter(|e| *e != actual) {
+                return Err(anyhow!("type_of expected '{expected}' but item is '{actual}'"));
+            }
+            with_inserts(state, |ins| set_interpdata(ins, &output_name, Value::String(actual.to_string()))).await;
+        }
+        "coerce" => {
+            let item = task.get("item").cloned().unwrap_or(Value::Null);
+            let to_type = as_string(&task, "to_type")?;
+            let output_name = as_string(&task, "output_name")?;
+            if !COERCE_TYPES.contains(&to_type.as_str()) {
+                return Err(anyhow!(
+                    "coerce.to_type must be one of {}, got '{to_type}'",
+                    COERCE_TYPES.join(", ")
+                ));
+            }
+            let value = coerce_value(&item, &to_type).ok_or_else(|| {
+                anyhow!(
+                    "coerce cannot convert {} '{}' to {to_type}",
+                    type_name(&item),
+                    value_to_string(&item)
+                )
+            })?;
+            with_inserts(state, |ins| set_interpdata(ins, &output_name, value)).await;
+        }
+        "string_split" => {
+            let text = as_string(&task, "text")?;
+            let separator = as_string(&task, "separator")?;
+            let output_name = as_string(&task, "output_name")?;
+            let limit = match task.get("limit") {
+                None | Some(Value::Null) => None,
+                Some(v) => {
+                    let limit = eval_math_index(v, &inserts_snapshot, &ctx)
+                        .map_err(|_| anyhow!("string_split.limit must be an int or math expression"))?;
+                    if limit < 1 {
+                        return Err(anyhow!("string_split.limit must be at least 1, got {limit}"));
+                    }
+                    Some(limit as usize)
+                }
+            };
+            let parts: Vec<String> = if separator.is_empty() {
+                let chars: Vec<char> = text.chars().collect();
+                let split_at = limit.map(|l| l - 1).unwrap_or(chars.len()).min(chars.len());
+                let mut parts: Vec<String> = chars[..split_at].iter().map(|c| c.to_string()).collect();
+                if split_at < chars.len() {
+                    parts.push(chars[split_at..].iter().collect());
+                }
+                parts
+            } else {
+                match limit {
+                    Some(limit) => text.splitn(limit, separator.as_str()).map(|s| s.to_string()).collect(),
+                    None => text.split(separator.as_str()).map(|s| s.to_string()).collect(),
+                }
+            };
+            logger.log(
+                "string_split",
+                json!({
+                    "output_name": output_name.clone(),
+                    "text_len": text.chars().count(),
+                    "len": parts.len(),
+                }),
+            );
+            let list = parts.into_iter().map(Value::String).collect();
+            with_inserts(state, |ins| set_interpdata(ins, &output_name, Value::Array(list))).await;
+        }
+        "string_replace" => {
+            let text = as_string(&task, "text")?;
+            let from = as_string(&task, "from")?;
+            let to = as_string(&task, "to")?;
+            let output_name = as_string(&task, "output_name")?;
+            let count = match task.get("count") {
+                None | Some(Value::Null) => None,
+                Some(v) => {
+                    let count = eval_math_index(v, &inserts_snapshot, &ctx)
+                        .map_err(|_| anyhow!("string_replace.count must be an int or math expression"))?;
+                    if count < 0 {
+                        return Err(anyhow!("string_replace.count cannot be negative, got {count}"));
+                    }
+                    Some(count as usize)
+                }
+            };
+            let (result, replaced) = if from.is_empty() {
+                (text, 0)
+            } else {
+                let found = text.matches(from.as_str()).count();
+                let replaced = count.map(|c| c.min(found)).unwrap_or(found);
+                (text.replacen(from.as_str(), &to, replaced), replaced)
+            };
+            logger.log(
+                "string_replace",
+                json!({
+                    "output_name": output_name.clone(),
+                    "replaced": replaced,
+                }),
+            );
+            with_inserts(state, |ins| set_interpdata(ins, &output_name, Value::String(result))).await;
+        }
+        "user_choice" => {
+            let list = as_array(&task, "list")?;
+            let description = as_string(&task, "description")?;
+            let output_name = as_string(&task, "output_name")?;
+            let multi_select = task.get("multi_select").and_then(Value::as_bool).unwrap_or(false);
+            if multi_select && !list.is_empty() {
+                let options = list.iter().map(value_to_string).collect::<Vec<_>>();
+                let indices = if dry_run {
+                    vec![0]
+                } else {
+                    await_with_cancel(
+                        &token,
+                        &io,
+                        io.select_indices(options, Some(description)),
+                    )
+                    .await?
+                };
+                let chosen = indices
+                    .iter()
+                    .filter_map(|&i| list.get(i).cloned())
+                    .collect::<Vec<_>>();
+                logger.log(
+                    "user_choice",
+                    json!({
+                        "output_name": output_name.clone(),
+                        "indices": indices,
+                        "choice": Value::Array(chosen.clone()),
+                    }),
+                );
+                with_inserts(state, |ins| set_interpdata(ins, &output_name, Value::Array(chosen))).await;
+            } else if list.is_empty() {
+                if !dry_run {
+                    let _ = await_with_cancel(
+                        &token,
+                        &io,
+                        io.select_index(Vec::new(), Some(description), true),
+                    )
+                    .await?;
+                }
+                with_inserts(state, |ins| set_interpdata(ins, &output_name, Value::Null)).await;
+            } else {
+                let options = list.iter().map(value_to_string).collect::<Vec<_>>();
+                let choice_index = if dry_run {
+                    0
+                } else {
+                    await_with_cancel(
+                        &token,
+                        &io,
+                        io.select_index(options, Some(description), true),
+                    )
+                    .await?
+                };
+                let choice = list
+                    .get(choice_index)
+                    .ok_or_else(|| anyhow!("Choice index out of bounds"))?
+                    .clone();
+                logger.log(
+                    "user_choice",
+                    json!({
+                        "output_name": output_name.clone(),
+                        "index": choice_index,
+                        "choice": value_to_string(&choice),
+                    }),
+                );
+                with_inserts(state, |ins| set_interpdata(ins, &output_name, choice)).await;
+            }
+        }
+        "user_input" => {
+            let prompt = as_string(&task, "prompt")?;
+            let output_name = as_string(&task, "output_name")?;
+            let validate_pattern = task.get("validate_pattern").and_then(Value::as_str).map(str::to_string);
+            let validate_error = task.get("validate_error").and_then(Value::as_str).map(str::to_string);
+            let masked = task.get("masked").and_then(Value::as_bool).unwrap_or(false);
+            if let Some(pattern) = &validate_pattern {
+                regex::Regex::new(pattern).map_err(|e| anyhow!("Invalid validate_pattern '{pattern}': {e}"))?;
+            }
+            let input = if dry_run {
+                task.get("default").map(value_to_string).unwrap_or_default()
+            } else {
+                await_with_cancel(
+                    &token,
+                    &io,
+                    io.user_input(prompt, validate_pattern, validate_error, masked),
+                )
+                .await?
+            };
+            let escaped = input
+                .replace(INSERT_START, &format!("{ESCAPE}{INSERT_START}"))
+                .replace(INSERT_STOP, &format!("{ESCAPE}{INSERT_STOP}"));
+            logger.log(
+                "user_input",
+                json!({
+                    "output_name": output_name.clone(),
+                    "value": if masked { "[masked]".to_string() } else { input },
+                }),
+            );
+            with_inserts(state, |ins| {
+                set_interpdata(ins, &output_name, Value::String(escaped))
+            })
+            .await;
+        }
+        "user_confirm" => {
+            let question = as_string(&task, "question")?;
+            let output_name = as_string(&task, "output_name")?;
+            let default = match task.get("default").and_then(Value::as_str) {
+                None => None,
+                Some("yes") => Some(true),
+                Some("no") => Some(false),
+                Some(other) => return Err(anyhow!("user_confirm.default must be 'yes' or 'no', got '{other}'")),
+            };
+            let confirmed = if dry_run {
+                default.unwrap_or(true)
+            } else {
+                await_with_cancel(&token, &io, io.confirm(question.clone(), default)).await?
+            };
+            let result = i64::from(confirmed);
+            logger.log(
+                "user_confirm",
+                json!({
+                    "output_name": output_name.clone(),
+                    "question": question,
+                    "result": result,
+                }),
+            );
+            with_inserts(state, |ins| set_interpdata(ins, &output_name, Value::Number(result.into()))).await;
+        }
+        "await_insert" => {
+            let name = as_string(&task, "name")?;
+            loop {
+                if token.is_cancelled() {
+                    return Err(anyhow!("cancelled"));
+                }
+                if state.lock().await.inserts().contains_key(&name) {
+                    break;
+                }
+                sleep(Duration::from_millis(50)).await;
+            }
+        }
+        "run_task" => {
+            let name = as_string(&task, "task_name")?;
+            let subtask = named_tasks
+                .get(&name)
+                .cloned()
+                .ok_or_else(|| anyhow!("Unknown task '{name}'"))?;
+            return execute_task(
+                state,
+                subtask,
+                completion_args.clone(),
+                named_tasks.clone(),
+                ctx.clone(),
+                io.clone(),
+                token,
+                format!("{runtime_label}/{name}"),
+                logger.clone(),
+                dry_run,
+            )
+            .await;
+        }
+        "map" | "filter_list" => {
+            let list = as_array(&task, "list")?;
+            let name = as_string(&task, "task_name")?;
+            let output_name = as_string(&task, "output_name")?;
+            let item_name = task.get("item_name").map(value_to_string).unwrap_or_else(|| "item".to_string());
+            let default_result = if cmd == "map" { "result" } else { "keep" };
+            let result_name = task
+                .get("result_name")
+                .map(value_to_string)
+                .unwrap_or_else(|| default_result.to_string());
+            let skip_errors = match task.get("on_error").and_then(Value::as_str) {
+                None | Some("fail") => false,
+                Some("skip") => true,
+                Some(other) => return Err(anyhow!("{cmd}.on_error must be 'skip' or 'fail', got '{other}'")),
+            };
+            let subtask = named_tasks
+                .get(&name)
+                .cloned()
+                .ok_or_else(|| anyhow!("Unknown task '{name}'"))?;
+            let child_label = format!("{runtime_label}/{name}");
+            let mut collected = Vec::with_capacity(list.len());
+            for item in list {
+                with_inserts(state.clone(), |ins| {
+                    delete_interpdata(ins, &result_name);
+                    set_interpdata(ins, &item_name, item.clone())
+                })
+                .await;
+                let outcome = execute_task(
+                    state.clone(),
+                    subtask.clone(),
+                    completion_args.clone(),
+                    named_tasks.clone(),
+                    ctx.clone(),
+                    io.clone(),
+                    token.child_token(),
+                    child_label.clone(),
+                    logger.clone(),
+                    dry_run,
+                )
+                .await;
+                clear_order_indices(state.clone(), &format!("order_index/{child_label}")).await;
+                match outcome {
+                    Err(e) if skip_errors && !is_cancelled(&e) => continue,
+                    Err(e) => return Err(e),
+                    Ok(outcome) => reject_escaping_outcome(cmd, &outcome)?,
+                }
+                let inserts = state.lock().await.inserts().clone();
+                let result = inserts
+                    .get(&result_name)
+                    .cloned()
+                    .ok_or_else(|| anyhow!("{cmd}: task '{name}' did not set '{result_name}'"))?;
+                if cmd == "map" {
+                    collected.push(result);
+                } else if eval_math_index(&result, &inserts, &ctx)
+                    .map_err(|e| anyhow!("filter_list: '{result_name}' must be a math result: {e}"))?
+                    != 0
+                {
+                    collected.push(item);
+                }
+            }
+            with_inserts(state, |ins| set_interpdata(ins, &output_name, Value::Array(collected))).await;
+        }
+        "reduce" => {
+            let list = as_array(&task, "list")?;
+            let name = as_string(&task, "task_name")?;
+            let output_name = as_string(&task, "output_name")?;
+            let item_name = task.get("item_name").map(value_to_string).unwrap_or_else(|| "item".to_string());
+            let accumulator_name = task
+                .get("accumulator_name")
+                .map(value_to_string)
+                .unwrap_or_else(|| "acc".to_string());
+            let initial = task
+                .get("initial")
+                .cloned()
+                .ok_or_else(|| anyhow!("reduce.initial is required"))?;
+            let subtask = named_tasks
+                .get(&name)
+                .cloned()
+                .ok_or_else(|| anyhow!("Unknown task '{name}'"))?;
+            let child_label = format!("{runtime_label}/{name}");
+            with_inserts(state.clone(), |ins| set_interpdata(ins, &accumulator_name, initial)).await;
+            for item in list {
+                with_inserts(state.clone(), |ins| set_interpdata(ins, &item_name, item)).await;
+                let outcome = execute_task(
+                    state.clone(),
+                    subtask.clone(),
+                    completion_args.clone(),
+                    named_tasks.clone(),
+                    ctx.clone(),
+                    io.clone(),
+                    token.child_token(),
+                    child_label.clone(),
+                    logger.clone(),
+                    dry_run,
+                )
+                .await?;
+                clear_order_indices(state.clone(), &format!("order_index/{child_label}")).await;
+                reject_escaping_outcome(cmd, &outcome)?;
+            }
+            let result = state
+                .lock()
+                .await
+                .inserts()
+                .get(&accumulator_name)
+                .cloned()
+                .ok_or_else(|| anyhow!("reduce: task '{name}' removed '{accumulator_name}'"))?;
+            with_inserts(state, |ins| set_interpdata(ins, &output_name, result)).await;
+        }
+        "parallel_wait" => {
+            let tasks = as_task_array(&task, "tasks")?;
+            let futures = tasks.into_iter().enumerate().map(|(index, t)| {
+                let child_label = format!("{}/{}", runtime_label, task_label(&t, index + 1));
+                execute_task(
+                    state.clone(),
+                    t,
+                    completion_args.clone(),
+                    named_tasks.clone(),
+                    ctx.clone(),
+                    io.clone(),
+                    token.child_token(),
+                    child_label,
+                    logger.clone(),
+                    dry_run,
+                )
+            });
+            let results = futures::future::join_all(futures).await;
+            for res in results {
+                res?;
+            }
+        }
+        "parallel_limit" => {
+            let tasks = as_task_array(&task, "tasks")?;
+            let max_concurrent = match task.get("max_concurrent") {
+                None | Some(Value::Null) => 4,
+                Some(v) => eval_math_index(v, &inserts_snapshot, &ctx)?,
+            };
+            if max_concurrent < 1 {
+                return Err(anyhow!("parallel_limit.max_concurrent must be at least 1, got {max_concurrent}"));
+            }
+            logger.log(
+                "parallel_limit_start",
+                json!({
+                    "tasks": tasks.len(),
+                    "max_concurrent": max_concurrent,
+                }),
+            );
+            let semaphore = Arc::new(tokio::sync::Semaphore::new(max_concurrent as usize));
+            let futures = tasks.into_iter().enumerate().map(|(index, t)| {
+                let child_label = format!("{}/{}", runtime_label, task_label(&t, index + 1));
+                let semaphore = semaphore.clone();
+                let run = execute_task(
+                    state.clone(),
+                    t,
+                    completion_args.clone(),
+                    named_tasks.clone(),
+                    ctx.clone(),
+                    io.clone(),
+                    token.child_token(),
+                    child_label,
+                    logger.clone(),
+                    dry_run,
+                );
+                async move {
+                    let _permit = semaphore.acquire().await?;
+                    run.await
+                }
+            });
+            let results = futures::future::join_all(futures).await;
+            for res in results {
+                res?;
+            }
+        }
+        "parallel_race" => {
+            let tasks = as_task_array(&task, "tasks")?;
+            let group = token.child_token();
             let mut futures = FuturesUnordered::new();
             for (index, t) in tasks.into_iter().enumerate() {
                 let child_label = format!("{}/{}", runtime_label, task_label(&t, index + 1));
@@ -913,6 +2028,7 @@ async fn execute_task(
                     group.child_token(),
                     child_label,
                     logger.clone(),
+                    dry_run,
                 ));
             }
             if let Some(res) = futures.next().await {
@@ -945,6 +2061,7 @@ async fn execute_task(
                     token.child_token(),
                     child_label,
                     logger.clone(),
+                    dry_run,
                 )
                 .await?;
                 match result {
@@ -953,50 +2070,126 @@ async fn execute_task(
                         let idx = find_label_index(&tasks, &target)?;
                         sub_index = idx as i64 + 2;
                     }
+                    TaskOutcome::Break => break,
+                    TaskOutcome::Continue => {
+                        state.lock().await.data.remove(&sub_index_label);
+                        return Ok(TaskOutcome::Continue);
+                    }
+                }
+                state.lock().await.set_i64(&sub_index_label, sub_index);
+            }
+            state.lock().await.data.remove(&sub_index_label);
+        }
+        "for" => {
+            let name_list_map = match task.get("name_list_map").cloned().unwrap_or(Value::Null) {
+                Value::String(s) => recursive_interpolate(&inserts_snapshot, Value::String(s), &ctx)?,
+                other => other,
+            };
+            let name_list_map = name_list_map
+                .as_object()
+                .ok_or_else(|| anyhow!("for.name_list_map must be object"))?
+                .clone();
+            let tasks = as_task_array(&task, "tasks")?;
+            let mut lists = Vec::new();
+            let mut item_names = Vec::new();
+            for (name, list_val) in name_list_map {
+                let list_value = recursive_interpolate(&inserts_snapshot, list_val, &ctx)?;
+                let list = list_value
+                    .as_array()
+                    .ok_or_else(|| anyhow!("for.name_list_map value for '{name}' must resolve to a list"))?
+                    .clone();
+                lists.push(list);
+                item_names.push(name);
+            }
+            let len = lists.first().map(|l| l.len()).unwrap_or(0);
+            if lists.iter().any(|l| l.len() != len) {
+                return Err(anyhow!("Lists have differing lengths"));
+            }
+            let counter_label = format!("order_index/{runtime_label}/counter");
+            let mut counter = state.lock().await.get_i64(&counter_label);
+            'iterations: while counter <= len as i64 {
+                if token.is_cancelled() {
+                    return Err(anyhow!("cancelled"));
+                }
+                let mut iteration_items = Map::new();
+                for (name, list) in item_names.iter().zip(lists.iter()) {
+                    let value = list[(counter - 1) as usize].clone();
+                    iteration_items.insert(name.clone(), value.clone());
+                    with_inserts(state.clone(), |ins| set_interpdata(ins, name, value)).await;
+                }
+                logger.log(
+                    "for_iteration",
+                    json!({
+                        "iteration": counter,
+                        "items": iteration_items,
+                    }),
+                );
+                let sub_index_label = format!("order_index/{runtime_label}");
+                let mut sub_index = state.lock().await.get_i64(&sub_index_label);
+                while sub_index <= tasks.len() as i64 {
+                    let subtask = tasks.get((sub_index - 1) as usize).cloned().unwrap();
+                    let child_label = format!(
+                        "{}/{}",
+                        runtime_label,
+                        task_label(&subtask, sub_index as usize)
+                    );
+                    let result = execute_task(
+                        state.clone(),
+                        subtask,
+                        completion_args.clone(),
+                        named_tasks.clone(),
+                        ctx.clone(),
+                        io.clone(),
+                        token.child_token(),
+                        child_label,
+                        logger.clone(),
+                        dry_run,
+                    )
+                    .await?;
+                    match result {
+                        TaskOutcome::None => sub_index += 1,
+                        TaskOutcome::Goto(target) => {
+                            let idx = find_label_index(&tasks, &target)?;
+                            sub_index = idx as i64 + 2;
+                        }
+                        TaskOutcome::Break => {
+                            state.lock().await.data.remove(&sub_index_label);
+                            break 'iterations;
+                        }
+                        TaskOutcome::Continue => break,
+                    }
+                    state.lock().await.set_i64(&sub_index_label, sub_index);
                 }
-                state.lock().await.set_i64(&sub_index_label, sub_index);
+                counter += 1;
+                state.lock().await.data.remove(&sub_index_label);
+                state.lock().await.set_i64(&counter_label, counter);
             }
-            state.lock().await.data.remove(&sub_index_label);
+            state.lock().await.data.remove(&counter_label);
         }
-        "for" => {
-            let name_list_map = task
-                .get("name_list_map")
-                .and_then(Value::as_object)
-                .ok_or_else(|| anyhow!("for.name_list_map must be object"))?
-                .clone();
+        "repeat" => {
+            let count = match task.get("count") {
+                Some(v) => eval_math_index(v, &inserts_snapshot, &ctx)?,
+                None => 0,
+            };
+            let counter_name = match task.get("counter_name").and_then(Value::as_str) {
+                Some(s) => Some(value_to_string(&interpolate_inserts(&inserts_snapshot, s, &ctx)?)),
+                None => None,
+            };
             let tasks = as_task_array(&task, "tasks")?;
-            let mut lists = Vec::new();
-            let mut item_names = Vec::new();
-            for (name, list_val) in name_list_map {
-                let list_value = recursive_interpolate(&inserts_snapshot, list_val, &ctx)?;
-                let list = list_value
-                    .as_array()
-                    .ok_or_else(|| anyhow!("for expects list values"))?
-                    .clone();
-                lists.push(list);
-                item_names.push(name);
-            }
-            let len = lists.first().map(|l| l.len()).unwrap_or(0);
-            if lists.iter().any(|l| l.len() != len) {
-                return Err(anyhow!("Lists have differing lengths"));
-            }
             let counter_label = format!("order_index/{runtime_label}/counter");
             let mut counter = state.lock().await.get_i64(&counter_label);
-            while counter <= len as i64 {
+            'iterations: while counter <= count {
                 if token.is_cancelled() {
                     return Err(anyhow!("cancelled"));
                 }
-                let mut iteration_items = Map::new();
-                for (name, list) in item_names.iter().zip(lists.iter()) {
-                    let value = list[(counter - 1) as usize].clone();
-                    iteration_items.insert(name.clone(), value.clone());
-                    with_inserts(state.clone(), |ins| set_interpdata(ins, name, value)).await;
+                if let Some(name) = counter_name.as_ref() {
+                    with_inserts(state.clone(), |ins| set_interpdata(ins, name, json!(counter))).await;
                 }
                 logger.log(
-                    "for_iteration",
+                    "repeat_iteration",
                     json!({
                         "iteration": counter,
-                        "items": iteration_items,
+                        "count": count,
                     }),
                 );
                 let sub_index_label = format!("order_index/{runtime_label}");
@@ -1018,6 +2211,81 @@ async fn execute_task(
                         token.child_token(),
                         child_label,
                         logger.clone(),
+                        dry_run,
+                    )
+                    .await?;
+                    match result {
+                        TaskOutcome::None => sub_index += 1,
+                        TaskOutcome::Goto(target) => {
+                            let idx = find_label_index(&tasks, &target)?;
+                            sub_index = idx as i64 + 2;
+                        }
+                        TaskOutcome::Break => {
+                            state.lock().await.data.remove(&sub_index_label);
+                            break 'iterations;
+                        }
+                        TaskOutcome::Continue => break,
+                    }
+                    state.lock().await.set_i64(&sub_index_label, sub_index);
+                }
+                counter += 1;
+                state.lock().await.data.remove(&sub_index_label);
+                state.lock().await.set_i64(&counter_label, counter);
+            }
+            state.lock().await.data.remove(&counter_label);
+        }
+        "while" => {
+            let condition = task
+                .get("condition")
+                .and_then(Value::as_str)
+                .ok_or_else(|| anyhow!("while.condition must be a string"))?
+                .to_string();
+            let max_iterations = match task.get("max_iterations") {
+                Some(v) => eval_math_index(v, &inserts_snapshot, &ctx)?,
+                None => 1000,
+            };
+            let tasks = as_task_array(&task, "tasks")?;
+            let counter_label = format!("order_index/{runtime_label}/counter");
+            let sub_index_label = format!("order_index/{runtime_label}");
+            let mut counter = state.lock().await.get_i64(&counter_label);
+            'iterations: loop {
+                if token.is_cancelled() {
+                    return Err(anyhow!("cancelled"));
+                }
+                let mut sub_index = state.lock().await.get_i64(&sub_index_label);
+                if sub_index == 1 {
+                    let inserts = state.lock().await.inserts().clone();
+                    if eval_math(&inserts, &condition, &ctx)? == 0 {
+                        break;
+                    }
+                    if counter > max_iterations {
+                        return Err(anyhow!(
+                            "while loop exceeded max_iterations ({max_iterations})"
+                        ));
+                    }
+                    logger.log("while_iteration", json!({ "iteration": counter }));
+                }
+                while sub_index <= tasks.len() as i64 {
+                    if token.is_cancelled() {
+                        return Err(anyhow!("cancelled"));
+                    }
+                    let subtask = tasks.get((sub_index - 1) as usize).cloned().unwrap();
+                    let child_label = format!(
+                        "{}/{}",
+                        runtime_label,
+                        task_label(&subtask, sub_index as usize)
+                    );
+                    let result = execute_task(
+                        state.clone(),
+                        subtask,
+                        completion_args.clone(),
+                        named_tasks.clone(),
+                        ctx.clone(),
+                        io.clone(),
+                        token.child_token(),
+                        child_label,
+                        logger.clone(),
+                        dry_run,
                     )
                     .await?;
                     match result {
@@ -1026,6 +2294,11 @@ async fn execute_task(
                             let idx = find_label_index(&tasks, &target)?;
                             sub_index = idx as i64 + 2;
                         }
+                        TaskOutcome::Break => {
+                            state.lock().await.data.remove(&sub_index_label);
+                            break 'iterations;
+                        }
+                        TaskOutcome::Continue => break,
                     }
                     state.lock().await.set_i64(&sub_index_label, sub_index);
                 }
@@ -1035,7 +2308,345 @@ async fn execute_task(
             }
             state.lock().await.data.remove(&counter_label);
         }
+        "if" => {
+            let branch_label = format!("order_index/{runtime_label}/branch");
+            let stored_branch = state.lock().await.data.get(&branch_label).and_then(Value::as_bool);
+            let take_then = match stored_branch {
+                Some(b) => b,
+                None => {
+                    let condition = task.get("condition").cloned().unwrap_or(Value::Null);
+                    let (truthy, value) = eval_condition(&condition, &inserts_snapshot, &ctx)?;
+                    state.lock().await.data.insert(branch_label.clone(), Value::Bool(truthy));
+                    logger.log(
+                        "if_branch",
+                        json!({
+                            "condition": task.get("condition").cloned().unwrap_or(Value::Null),
+                            "value": value,
+                            "branch": if truthy { "then" } else { "else" },
+                        }),
+                    );
+                    truthy
+                }
+            };
+            let tasks = if_branch_tasks(&task, take_then)?;
+            let sub_index_label = format!("order_index/{runtime_label}");
+            let mut sub_index = state.lock().await.get_i64(&sub_index_label);
+            while sub_index <= tasks.len() as i64 {
+                if token.is_cancelled() {
+                    return Err(anyhow!("cancelled"));
+                }
+                let subtask = tasks.get((sub_index - 1) as usize).cloned().unwrap();
+                let child_label =
+                    format!("{}/{}", runtime_label, task_label(&subtask, sub_index as usize));
+                let result = execute_task(
+                    state.clone(),
+                    subtask,
+                    completion_args.clone(),
+                    named_tasks.clone(),
+                    ctx.clone(),
+                    io.clone(),
+                    token.child_token(),
+                    child_label,
+                    logger.clone(),
+                    dry_run,
+                )
+                .await?;
+                match result {
+                    TaskOutcome::None => sub_index += 1,
+                    TaskOutcome::Goto(target) => match find_label_index(&tasks, &target) {
+                        Ok(idx) => sub_index = idx as i64 + 2,
+                        Err(_) => {
+                            // Labels outside the branch belong to the enclosing task list.
+                            let mut st = state.lock().await;
+                            st.data.remove(&sub_index_label);
+                            st.data.remove(&branch_label);
+                            return Ok(TaskOutcome::Goto(target));
+                        }
+                    },
+                    outcome @ (TaskOutcome::Break | TaskOutcome::Continue) => {
+                        let mut st = state.lock().await;
+                        st.data.remove(&sub_index_label);
+                        st.data.remove(&branch_label);
+                        return Ok(outcome);
+                    }
+                }
+                state.lock().await.set_i64(&sub_index_label, sub_index);
+            }
+            let mut st = state.lock().await;
+            st.data.remove(&sub_index_label);
+            st.data.remove(&branch_label);
+        }
+        "try" => {
+            let tasks = as_task_array(&task, "tasks")?;
+            let on_error = as_task_array(&task, "on_error")?;
+            let error_name = task.get("error_name").and_then(Value::as_str).map(|s| s.to_string());
+            let sub_index_label = format!("order_index/{runtime_label}");
+            let error_label = format!("order_index/{runtime_label}/error");
+            let mut in_handler = state.lock().await.data.contains_key(&error_label);
+            loop {
+                let phase_tasks = if in_handler { &on_error } else { &tasks };
+                let attempt: Result<TaskOutcome> = async {
+                    let mut sub_index = state.lock().await.get_i64(&sub_index_label);
+                    while sub_index <= phase_tasks.len() as i64 {
+                        if token.is_cancelled() {
+                            return Err(anyhow!("cancelled"));
+                        }
+                        let subtask = phase_tasks.get((sub_index - 1) as usize).cloned().unwrap();
+                        let child_label =
+                            format!("{}/{}", runtime_label, task_label(&subtask, sub_index as usize));
+                        let result = execute_task(
+                            state.clone(),
+                            subtask,
+                            completion_args.clone(),
+                            named_tasks.clone(),
+                            ctx.clone(),
+                            io.clone(),
+                            token.child_token(),
+                            child_label,
+                            logger.clone(),
+                            dry_run,
+                        )
+                        .await?;
+                        match result {
+                            TaskOutcome::None => sub_index += 1,
+                            TaskOutcome::Goto(target) => match find_label_index(phase_tasks, &target) {
+                                Ok(idx) => sub_index = idx as i64 + 2,
+                                Err(_) => return Ok(TaskOutcome::Goto(target)),
+                            },
+                            outcome => return Ok(outcome),
+                        }
+                        state.lock().await.set_i64(&sub_index_label, sub_index);
+                    }
+                    Ok(TaskOutcome::None)
+                }
+                .await;
+                match attempt {
+                    Ok(outcome) => {
+                        let mut st = state.lock().await;
+                        st.data.remove(&sub_index_label);
+                        st.data.remove(&error_label);
+                        drop(st);
+                        if !matches!(outcome, TaskOutcome::None) {
+                            return Ok(outcome);
+                        }
+                        break;
+                    }
+                    Err(e) if in_handler || is_cancelled(&e) || token.is_cancelled() => return Err(e),
+                    Err(e) => {
+                        let message = e.to_string();
+                        logger.log("try_error", json!({ "error": message.clone() }));
+                        clear_order_indices(state.clone(), &sub_index_label).await;
+                        state.lock().await.data.insert(error_label.clone(), Value::Bool(true));
+                        if let Some(error_name) = &error_name {
+                            with_inserts(state.clone(), |ins| {
+                                set_interpdata(ins, error_name, recursive_escape(Value::String(message)))
+                            })
+                            .await;
+                        }
+                        in_handler = true;
+                    }
+                }
+            }
+        }
+        "retry" => {
+            let inner = task
+                .get("task")
+                .and_then(Value::as_object)
+                .cloned()
+                .ok_or_else(|| anyhow!("retry.task must be a task object"))?;
+            let max_attempts = match task.get("max_attempts") {
+                Some(v) => eval_math_index(v, &inserts_snapshot, &ctx)?,
+                None => 3,
+            };
+            let delay_val = task.get("delay_seconds").cloned().unwrap_or(Value::Null);
+            let delay = if let Some(s) = delay_val.as_str() {
+                eval_math(&inserts_snapshot, s, &ctx)? as f64
+            } else {
+                delay_val.as_f64().unwrap_or(1.0)
+            };
+            let sub_index_label = format!("order_index/{runtime_label}");
+            let attempt_label = format!("order_index/{runtime_label}/attempt");
+            let mut attempt = state.lock().await.get_i64(&attempt_label);
+            loop {
+                let child_label = format!("{}/{}", runtime_label, task_label(&inner, 1));
+                let result = execute_task(
+                    state.clone(),
+                    inner.clone(),
+                    completion_args.clone(),
+                    named_tasks.clone(),
+                    ctx.clone(),
+                    io.clone(),
+                    token.child_token(),
+                    child_label,
+                    logger.clone(),
+                    dry_run,
+                )
+                .await;
+                match result {
+                    Ok(outcome) => {
+                        state.lock().await.data.remove(&attempt_label);
+                        return Ok(outcome);
+                    }
+                    Err(e) if is_cancelled(&e) || token.is_cancelled() => return Err(e),
+                    Err(e) => {
+                        let remaining = max_attempts - attempt;
+                        logger.log(
+                            "retry_attempt",
+                            json!({
+                                "attempt": attempt,
+                                "remaining": remaining.max(0),
+                                "error": e.to_string(),
+                            }),
+                        );
+                        clear_order_indices(state.clone(), &sub_index_label).await;
+                        if remaining <= 0 {
+                            return Err(e);
+                        }
+                        attempt += 1;
+                        state.lock().await.set_i64(&attempt_label, attempt);
+                        tokio::select! {
+                            _ = sleep(Duration::from_secs_f64(delay.max(0.0))) => {}
+                            _ = token.cancelled() => return Err(anyhow!("cancelled")),
+                        }
+                    }
+                }
+            }
+        }
+        "benchmark" => {
+            let inner = task
+                .get("task")
+                .and_then(Value::as_object)
+                .cloned()
+                .ok_or_else(|| anyhow!("benchmark.task must be a task object"))?;
+            let output_name = as_string(&task, "output_name")?;
+            let label = task.get("label").and_then(Value::as_str).unwrap_or("").to_string();
+            let inner_cmd = inner.get("cmd").and_then(Value::as_str).unwrap_or("").to_string();
+            let child_label = format!("{}/{}", runtime_label, task_label(&inner, 1));
+            let started = std::time::Instant::now();
+            let outcome = execute_task(
+                state.clone(),
+                inner,
+                completion_args.clone(),
+                named_tasks.clone(),
+                ctx.clone(),
+                io.clone(),
+                token.child_token(),
+                child_label,
+                logger.clone(),
+                dry_run,
+            )
+            .await?;
+            let elapsed_ms = started.elapsed().as_millis() as i64;
+            logger.log(
+                "benchmark",
+                json!({
+                    "label": label,
+                    "cmd": inner_cmd,
+                    "elapsed_ms": elapsed_ms,
+                }),
+            );
+            with_inserts(state, |ins| set_interpdata(ins, &output_name, Value::Number(elapsed_ms.into()))).await;
+            return Ok(outcome);
+        }
+        "timeout" => {
+            let inner = task
+                .get("task")
+                .and_then(Value::as_object)
+                .cloned()
+                .ok_or_else(|| anyhow!("timeout.task must be a task object"))?;
+            let on_timeout = match task.get("on_timeout") {
+                None | Some(Value::Null) => None,
+                Some(Value::Object(obj)) => Some(vec![obj.clone()]),
+                Some(_) => Some(as_task_array(&task, "on_timeout")?),
+            };
+            let seconds_val = task.get("seconds").cloned().unwrap_or(Value::Null);
+            let seconds = if let Some(s) = seconds_val.as_str() {
+                eval_math(&inserts_snapshot, s, &ctx)? as f64
+            } else {
+                seconds_val
+                    .as_f64()
+                    .ok_or_else(|| anyhow!("timeout.seconds must be a number or math expression"))?
+            };
+            let sub_index_label = format!("order_index/{runtime_label}");
+            let expired_label = format!("order_index/{runtime_label}/expired");
+            if !state.lock().await.data.contains_key(&expired_label) {
+                let inner_token = token.child_token();
+                let child_label = format!("{}/{}", runtime_label, task_label(&inner, 1));
+                let run = execute_task(
+                    state.clone(),
+                    inner,
+                    completion_args.clone(),
+                    named_tasks.clone(),
+                    ctx.clone(),
+                    io.clone(),
+                    inner_token.clone(),
+                    child_label,
+                    logger.clone(),
+                    dry_run,
+                );
+                match tokio::time::timeout(Duration::from_secs_f64(seconds.max(0.0)), run).await {
+                    Ok(outcome) => return outcome,
+                    Err(_) => {
+                        inner_token.cancel();
+                        io.cancel_input();
+                        clear_order_indices(state.clone(), &sub_index_label).await;
+                        logger.log("timeout", json!({ "seconds": seconds }));
+                    }
+                }
+                if on_timeout.is_none() {
+                    return Err(anyhow!("Task timed out after {seconds} seconds"));
+                }
+                state.lock().await.data.insert(expired_label.clone(), Value::Bool(true));
+            }
+            let tasks = on_timeout.unwrap_or_default();
+            let mut sub_index = state.lock().await.get_i64(&sub_index_label);
+            while sub_index <= tasks.len() as i64 {
+                if token.is_cancelled() {
+                    return Err(anyhow!("cancelled"));
+                }
+                let subtask = tasks.get((sub_index - 1) as usize).cloned().unwrap();
+                let child_label =
+                    format!("{}/{}", runtime_label, task_label(&subtask, sub_index as usize));
+                let result = execute_task(
+                    state.clone(),
+                    subtask,
+                    completion_args.clone(),
+                    named_tasks.clone(),
+                    ctx.clone(),
+                    io.clone(),
+                    token.child_token(),
+                    child_label,
+                    logger.clone(),
+                    dry_run,
+                )
+                .await?;
+                match result {
+                    TaskOutcome::None => sub_index += 1,
+                    TaskOutcome::Goto(target) => match find_label_index(&tasks, &target) {
+                        Ok(idx) => sub_index = idx as i64 + 2,
+                        Err(_) => {
+                            let mut st = state.lock().await;
+                            st.data.remove(&sub_index_label);
+                            st.data.remove(&expired_label);
+                            return Ok(TaskOutcome::Goto(target));
+                        }
+                    },
+                    outcome @ (TaskOutcome::Break | TaskOutcome::Continue) => {
+                        let mut st = state.lock().await;
+                        st.data.remove(&sub_index_label);
+                        st.data.remove(&expired_label);
+                        return Ok(outcome);
+                    }
+                }
+                state.lock().await.set_i64(&sub_index_label, sub_index);
+            }
+            let mut st = state.lock().await;
+            st.data.remove(&sub_index_label);
+            st.data.remove(&expired_label);
+        }
         "label" => {}
+        "break" => return Ok(TaskOutcome::Break),
+        "continue" => return Ok(TaskOutcome::Continue),
         "set" => {
             let item = task.get("item").cloned().unwrap_or(Value::Null);
             let output_name = as_string(&task, "output_name")?;
@@ -1089,6 +2700,11 @@ async fn execute_task(
                 .and_then(Value::as_array)
                 .ok_or_else(|| anyhow!("goto_map.target_maps must be array"))?;
 
+            let case_insensitive = task
+                .get("case_insensitive")
+                .and_then(Value::as_bool)
+                .unwrap_or(false);
+
             let mut interp_error = false;
             let value_text = match interpolate_inserts(&inserts_snapshot, &value_text, &ctx) {
                 Ok(v) => value_to_string(&v),
@@ -1124,7 +2740,7 @@ async fn execute_task(
                     let (k, v) = obj.iter().next().ok_or_else(|| anyhow!("target_maps entry empty"))?;
                     let key = value_to_string(&interpolate_inserts(&inserts_snapshot, k, &ctx)?);
                     let val = value_to_string(&interpolate_inserts(&inserts_snapshot, v.as_str().unwrap_or(""), &ctx)?);
-                    if wildcard_match(&key, &value_text) {
+                    if wildcard_match(&key, &value_text, case_insensitive) {
                         target = Some(val);
                         break;
                     }
@@ -1143,6 +2759,57 @@ async fn execute_task(
                 return Ok(TaskOutcome::Goto(target));
             }
         }
+        "switch" => {
+            let value_text = as_string(&task, "value")?;
+            let value_text = value_to_string(&interpolate_inserts(&inserts_snapshot, &value_text, &ctx)?);
+            let cases = task
+                .get("cases")
+                .and_then(Value::as_array)
+                .ok_or_else(|| anyhow!("switch.cases must be array"))?;
+            let mut matched = None;
+            for entry in cases {
+                let obj = entry.as_object().ok_or_else(|| anyhow!("switch.cases entry must be object"))?;
+                let (k, v) = obj.iter().next().ok_or_else(|| anyhow!("switch.cases entry empty"))?;
+                let pattern = value_to_string(&interpolate_inserts(&inserts_snapshot, k, &ctx)?);
+                if wildcard_match(&pattern, &value_text, false) {
+                    matched = Some((pattern, v.clone()));
+                    break;
+                }
+            }
+            let (case, inner) = match matched {
+                Some((pattern, inner)) => (Some(pattern), Some(inner)),
+                None => (None, task.get("default").cloned()),
+            };
+            logger.log(
+                "switch",
+                json!({
+                    "value": value_text,
+                    "case": case,
+                    "has_default": task.contains_key("default"),
+                }),
+            );
+            let Some(inner) = inner else {
+                return Ok(TaskOutcome::None);
+            };
+            let inner = inner
+                .as_object()
+                .cloned()
+                .ok_or_else(|| anyhow!("switch cases and default must be task objects"))?;
+            let child_label = format!("{}/{}", runtime_label, task_label(&inner, 1));
+            return execute_task(
+                state.clone(),
+                inner,
+                completion_args.clone(),
+                named_tasks.clone(),
+                ctx.clone(),
+                io.clone(),
+                token.child_token(),
+                child_label,
+                logger.clone(),
+                dry_run,
+            )
+            .await;
+        }
         "replace_map" => {
             let item = task.get("item").cloned().unwrap_or(Value::Null);
             let output_name = as_string(&task, "output_name")?;
@@ -1164,11 +2831,28 @@ async fn execute_task(
                     "item_preview": preview_value(&item, PREVIEW_LONG),
                 }),
             );
-            let result = replace_map(item, &maps, &inserts_snapshot, &ctx, repeat_until_done)?;
+            let case_insensitive = task
+                .get("case_insensitive")
+                .and_then(Value::as_bool)
+                .unwrap_or(false);
+            let result = replace_map(item, &maps, &inserts_snapshot, &ctx, repeat_until_done, case_insensitive)?;
             with_inserts(state, |ins| set_interpdata(ins, &output_name, result)).await;
         }
         "show_inserts" => {
-            let inserts = state.lock().await.inserts().clone();
+            let pattern = task.get("pattern").map(value_to_string);
+            let keys = task
+                .get("keys")
+                .and_then(Value::as_array)
+                .map(|keys| keys.iter().map(value_to_string).collect::<Vec<_>>());
+            let inserts: Map<String, Value> = state
+                .lock()
+                .await
+                .inserts()
+                .iter()
+                .filter(|(k, _)| pattern.as_ref().is_none_or(|p| wildcard_match(p, k, false)))
+                .filter(|(k, _)| keys.as_ref().is_none_or(|keys| keys.contains(k)))
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect();
             let text = serde_json::to_string_pretty(&Value::Object(inserts))?;
             let _ = await_with_cancel(
                 &token,
@@ -1177,6 +2861,59 @@ async fn execute_task(
             )
             .await?;
         }
+        "random_int" => {
+            let min_val = task.get("min").cloned().unwrap_or(Value::Null);
+            let max_val = task.get("max").cloned().unwrap_or(Value::Null);
+            let min = eval_math_index(&min_val, &inserts_snapshot, &ctx)
+                .map_err(|_| anyhow!("random_int.min must be an int or math expression"))?;
+            let max = eval_math_index(&max_val, &inserts_snapshot, &ctx)
+                .map_err(|_| anyhow!("random_int.max must be an int or math expression"))?;
+            let output_name = as_string(&task, "output_name")?;
+            if min > max {
+                return Err(anyhow!("random_int.min ({min}) is greater than max ({max})"));
+            }
+            let seed = match task.get("seed") {
+                None | Some(Value::Null) => None,
+                Some(v) => Some(
+                    eval_math_index(v, &inserts_snapshot, &ctx)
+                        .map_err(|_| anyhow!("random_int.seed must be an int or math expression"))?,
+                ),
+            };
+            let value = match seed {
+                Some(seed) => SmallRng::seed_from_u64(seed as u64).gen_range(min..=max),
+                None => rand::thread_rng().gen_range(min..=max),
+            };
+            logger.log(
+                "random_int",
+                json!({
+                    "output_name": output_name.clone(),
+                    "min": min,
+                    "max": max,
+                    "value": value,
+                }),
+            );
+            with_inserts(state, |ins| set_interpdata(ins, &output_name, Value::Number(value.into()))).await;
+        }
+        "random_float" => {
+            let min = match task.get("min") {
+                Some(v) => eval_math_float(v, &inserts_snapshot, &ctx)
+                    .map_err(|_| anyhow!("random_float.min must be a number or math expression"))?,
+                None => 0.0,
+            };
+            let max = match task.get("max") {
+                Some(v) => eval_math_float(v, &inserts_snapshot, &ctx)
+                    .map_err(|_| anyhow!("random_float.max must be a number or math expression"))?,
+                None => 1.0,
+            };
+            let output_name = as_string(&task, "output_name")?;
+            if min > max {
+                return Err(anyhow!("random_float.min ({min}) is greater than max ({max})"));
+            }
+            let value = min + random::<f64>() * (max - min);
+            let number = serde_json::Number::from_f64(value)
+                .ok_or_else(|| anyhow!("random_float produced a non-finite value ({value})"))?;
+            with_inserts(state, |ins| set_interpdata(ins, &output_name, Value::Number(number))).await;
+        }
         "random_choice" => {
             let list = as_array(&task, "list")?;
             let output_name = as_string(&task, "output_name")?;
@@ -1201,7 +2938,7 @@ async fn execute_task(
             with_inserts(state, |ins| {
                 let keys: Vec<String> = ins.keys().cloned().collect();
                 for k in keys {
-                    if wildcards.iter().any(|w| wildcard_match(&value_to_string(w), &k)) {
+                    if wildcards.iter().any(|w| wildcard_match(&value_to_string(w), &k, false)) {
                         delete_interpdata(ins, &k);
                         deleted.push(k);
                     }
@@ -1222,7 +2959,7 @@ async fn execute_task(
             with_inserts(state, |ins| {
                 let keys: Vec<String> = ins.keys().cloned().collect();
                 for k in keys {
-                    if !wildcards.iter().any(|w| wildcard_match(&value_to_string(w), &k)) {
+                    if !wildcards.iter().any(|w| wildcard_match(&value_to_string(w), &k, false)) {
                         delete_interpdata(ins, &k);
                         deleted.push(k);
                     }
@@ -1258,6 +2995,103 @@ async fn execute_task(
             })
             .await;
         }
+        "read" => {
+            let path = as_string(&task, "path")?;
+            let output_name = as_string(&task, "output_name")?;
+            let format = task.get("format").and_then(Value::as_str).unwrap_or("text").to_string();
+            let resolved = resolve_path(&ctx, &path);
+            let content = fs::read_to_string(&resolved)
+                .map_err(|e| anyhow!("read path '{}' could not be read: {e}", resolved.display()))?;
+            let value = match format.as_str() {
+                "text" => Value::String(content.clone()),
+                "json" => serde_json::from_str(&content)
+                    .map_err(|e| anyhow!("read path '{}' is not valid JSON: {e}", resolved.display()))?,
+                "json5" => json5::from_str(&content)
+                    .map_err(|e| anyhow!("read path '{}' is not valid JSON5: {e}", resolved.display()))?,
+                other => return Err(anyhow!("read.format must be 'text', 'json' or 'json5', got '{other}'")),
+            };
+            logger.log(
+                "read",
+                json!({
+                    "path": resolved.to_string_lossy(),
+                    "bytes": content.len(),
+                }),
+            );
+            with_inserts(state, |ins| set_interpdata(ins, &output_name, recursive_escape(value))).await;
+        }
+        "http_get" => {
+            let url = as_string(&task, "url")?;
+            let output_name = as_string(&task, "output_name")?;
+            let status_name = task.get("status_name").and_then(Value::as_str).map(|s| s.to_string());
+            let allow_error_status = task.get("allow_error_status").and_then(Value::as_bool).unwrap_or(false);
+            let parse_json = task.get("parse_json").and_then(Value::as_bool).unwrap_or(false);
+            let timeout_val = task.get("timeout_seconds").cloned().unwrap_or(Value::Null);
+            let timeout_seconds = match &timeout_val {
+                Value::Null => 30.0,
+                Value::String(s) => eval_math(&inserts_snapshot, s, &ctx)? as f64,
+                v => v.as_f64().ok_or_else(|| anyhow!("http_get.timeout_seconds must be a number"))?,
+            };
+            if dry_run {
+                logger.log("dry_run_skip", json!({ "cmd": "http_get", "target": url }));
+                with_inserts(state, |ins| {
+                    set_interpdata(ins, &output_name, Value::String(String::new()));
+                    if let Some(status_name) = &status_name {
+                        set_interpdata(ins, status_name, Value::Number(200.into()));
+                    }
+                })
+                .await;
+                return Ok(TaskOutcome::None);
+            }
+            let mut request = reqwest::Client::new()
+                .get(&url)
+                .timeout(Duration::from_secs_f64(timeout_seconds.max(0.0)));
+            if let Some(headers) = task.get("headers") {
+                let headers = headers
+                    .as_object()
+                    .ok_or_else(|| anyhow!("http_get.headers must be an object"))?;
+                for (name, value) in headers {
+                    request = request.header(name, value_to_string(&recursive_unescape(value.clone())));
+                }
+            }
+            let fetch = async {
+                let res = request.send().await?;
+                let status = res.status();
+                let body = res.text().await?;
+                Ok::<_, anyhow::Error>((status, body))
+            };
+            let (status, body) = tokio::select! {
+                res = fetch => res.map_err(|e| anyhow!("http_get '{url}' failed: {e}"))?,
+                _ = token.cancelled() => return Err(anyhow!("cancelled")),
+            };
+            logger.log(
+                "http_get",
+                json!({
+                    "url": url.clone(),
+                    "status": status.as_u16(),
+                    "bytes": body.len(),
+                }),
+            );
+            if !status.is_success() && !allow_error_status {
+                return Err(anyhow!(
+                    "http_get '{url}' returned {status}: {}",
+                    preview_text(&body, PREVIEW_LONG)
+                ));
+            }
+            let value = if parse_json {
+                serde_json::from_str(&body).map_err(|e| {
+                    anyhow!("http_get '{url}' did not return valid JSON ({e}): {}", preview_text(&body, PREVIEW_LONG))
+                })?
+            } else {
+                Value::String(body)
+            };
+            with_inserts(state, |ins| {
+                set_interpdata(ins, &output_name, recursive_escape(value));
+                if let Some(status_name) = &status_name {
+                    set_interpdata(ins, status_name, Value::Number(status.as_u16().into()));
+                }
+            })
+            .await;
+        }
         "write" => {
             let item = task.get("item").cloned().unwrap_or(Value::Null);
             let path = as_string(&task, "path")?;
@@ -1276,6 +3110,16 @@ async fn execute_task(
                 v => serde_json::to_string(&v)?,
             };
             let bytes = content.len();
+            if dry_run {
+                logger.log(
+                    "dry_run_skip",
+                    json!({
+                        "cmd": "write",
+                        "target": resolved.to_string_lossy(),
+                    }),
+                );
+                return Ok(TaskOutcome::None);
+            }
             fs::write(&resolved, &content)?;
             logger.log(
                 "write",
@@ -1290,6 +3134,10 @@ async fn execute_task(
             let voice_path = as_string(&task, "voice_path")?;
             let voice_path = resolve_path(&ctx, &voice_path);
             let voice_path_str = voice_path.to_string_lossy().to_string();
+            if dry_run {
+                logger.log("dry_run_skip", json!({ "cmd": "speak", "target": voice_path_str }));
+                return Ok(TaskOutcome::None);
+            }
             logger.log(
                 "speak",
                 json!({
@@ -1306,6 +3154,15 @@ async fn execute_task(
         "chat" => {
             let messages = task.get("messages").cloned().unwrap_or(Value::Null);
             let output_name = as_string(&task, "output_name")?;
+            if dry_run {
+                let mock = task
+                    .get("dry_run_response")
+                    .cloned()
+                    .unwrap_or_else(|| Value::String(String::new()));
+                logger.log("dry_run_skip", json!({ "cmd": "chat", "target": output_name.clone() }));
+                with_inserts(state, |ins| set_interpdata(ins, &output_name, mock)).await;
+                return Ok(TaskOutcome::None);
+            }
 
             let mut completion = (*completion_args).clone();
             if let Some(extra) = task.get("extra_body").and_then(Value::as_object) {
@@ -1334,6 +3191,32 @@ async fn execute_task(
                 .remove("stop_str")
                 .and_then(|v| v.as_str().map(|s| s.to_string()))
                 .unwrap_or_default();
+            let named_sections = match completion.remove("sections") {
+                None | Some(Value::Null) => None,
+                Some(Value::Object(obj)) => Some(
+                    obj.into_iter()
+                        .map(|(name, markers)| {
+                            let pair = markers
+                                .as_array()
+                                .filter(|arr| arr.len() == 2)
+                                .map(|arr| (value_to_string(&arr[0]), value_to_string(&arr[1])))
+                                .filter(|(start, stop)| !start.is_empty() && !stop.is_empty())
+                                .ok_or_else(|| {
+                                    anyhow!("chat.sections.{name} must be a [start_str, stop_str] pair of non-empty strings")
+                                })?;
+                            Ok((name, pair))
+                        })
+                        .collect::<Result<Vec<_>>>()?,
+                ),
+                Some(_) => return Err(anyhow!("chat.sections must be an object")),
+            };
+            if named_sections.is_some() && !start_str.is_empty() {
+                return Err(anyhow!("chat.sections cannot be combined with start_str and stop_str"));
+            }
+            let sections: Vec<(String, String)> = match named_sections.as_ref() {
+                Some(named) => named.iter().map(|(_, pair)| pair.clone()).collect(),
+                None => vec![(start_str.clone(), stop_str.clone())],
+            };
             let hide_start_str = completion
                 .remove("hide_start_str")
                 .and_then(|v| v.as_str().map(|s| s.to_string()))
@@ -1357,6 +3240,14 @@ async fn execute_task(
                 .remove("choices_list")
                 .and_then(|v| v.as_array().cloned())
                 .map(|arr| arr.iter().map(value_to_string).collect::<Vec<_>>());
+            let json_output = match completion.remove("json_output") {
+                Some(Value::Bool(b)) => b,
+                Some(Value::String(s)) => s == "true",
+                _ => false,
+            };
+            let usage_name = completion
+                .remove("usage_name")
+                .and_then(|v| v.as_str().map(|s| s.to_string()));
             let voice_path = completion
                 .remove("voice_path")
                 .and_then(|v| v.as_str().map(|s| s.to_string()));
@@ -1371,16 +3262,37 @@ async fn execute_task(
                 .remove("api_key")
                 .and_then(|v| v.as_str().map(|s| s.to_string()))
                 .unwrap_or_else(|| "unused".to_string());
-            let extra_body = completion
+            let mut extra_body = completion
                 .remove("extra_body")
                 .and_then(|v| v.as_object().cloned())
                 .unwrap_or_default();
+            let grammar = match (completion.remove("gbnf_grammar"), completion.remove("gbnf_file")) {
+                (Some(_), Some(_)) => return Err(anyhow!("chat accepts either gbnf_grammar or gbnf_file, not both")),
+                (Some(grammar), None) => Some(value_to_string(&recursive_unescape(grammar))),
+                (None, Some(path)) => {
+                    let resolved = resolve_path(&ctx, &value_to_string(&path));
+                    Some(fs::read_to_string(&resolved).map_err(|e| {
+                        anyhow!("gbnf_file '{}' could not be read: {e}", resolved.display())
+                    })?)
+                }
+                (None, None) => None,
+            };
+            if let Some(grammar) = grammar {
+                extra_body.insert("grammar".to_string(), Value::String(grammar));
+            }
+            // OpenAI only reports usage for streamed responses when asked to.
+            let has_stream_options =
+                completion.contains_key("stream_options") || extra_body.contains_key("stream_options");
+            if usage_name.is_some() && !has_stream_options {
+                completion.insert("stream_options".to_string(), json!({"include_usage": true}));
+            }
 
             let messages = interpolate_messages(messages, &inserts_snapshot, &ctx)?;
             let messages_for_log = messages.clone();
 
             completion.remove("line");
             completion.remove("traceback_label");
+            completion.remove("dry_run_response");
 
             logger.log(
                 "chat_start",
@@ -1407,6 +3319,78 @@ async fn execute_task(
             } else {
                 None
             };
+            let tool_executor: Option<ToolExecutor> = if completion.contains_key("tools") {
+                let state = state.clone();
+                let completion_args = completion_args.clone();
+                let named_tasks = named_tasks.clone();
+                let ctx = ctx.clone();
+                let io = io.clone();
+                let token = token.clone();
+                let logger = logger.clone();
+                let runtime_label = runtime_label.clone();
+                Some(Arc::new(move |name: String, arguments: Value| {
+                    let subtask = named_tasks.get(&name).cloned();
+                    let state = state.clone();
+                    let completion_args = completion_args.clone();
+                    let named_tasks = named_tasks.clone();
+                    let ctx = ctx.clone();
+                    let io = io.clone();
+                    let token = token.child_token();
+                    let label = format!("{runtime_label}/{name}");
+                    let logger = logger.clone();
+                    async move {
+                        let subtask = subtask.ok_or_else(|| anyhow!("chat requested unknown tool '{name}'"))?;
+                        let arguments = match arguments {
+                            Value::Object(args) => args,
+                            _ => Map::new(),
+                        };
+                        // Tool arguments only live for the duration of the call; the previous
+                        // values of these keys are put back afterwards.
+                        let saved: Vec<(String, Option<Value>)> = {
+                            let mut st = state.lock().await;
+                            let ins = st.inserts_mut();
+                            let saved = arguments
+                                .keys()
+                                .map(String::as_str)
+                                .chain(["tool_result"])
+                                .map(|k| (k.to_string(), ins.get(k).cloned()))
+                                .collect();
+                            for (k, v) in arguments {
+                                set_interpdata(ins, &k, recursive_escape(v));
+                            }
+                            delete_interpdata(ins, "tool_result");
+                            saved
+                        };
+                        let outcome = execute_task(
+                            state.clone(),
+                            subtask,
+                            completion_args,
+                            named_tasks,
+                            ctx,
+                            io,
+                            token,
+                            label,
+                            logger,
+                            dry_run,
+                        )
+                        .await;
+                        let mut st = state.lock().await;
+                        let ins = st.inserts_mut();
+                        let result = ins.get("tool_result").cloned();
+                        for (k, previous) in saved {
+                            match previous {
+                                Some(v) => set_interpdata(ins, &k, v),
+                                None => delete_interpdata(ins, &k),
+                            }
+                        }
+                        outcome?;
+                        Ok(result.unwrap_or(Value::Null))
+                    }
+                    .boxed_local()
+                }))
+            } else {
+                None
+            };
             let io_clone = io.clone();
             let tts_clone = tts_writer.clone();
             let mut on_text = move |text: &str| -> Result<()> {
@@ -1426,13 +3410,13 @@ async fn execute_task(
                 outputs,
                 visual_output,
                 raw,
+                usage,
             } = loop {
                 let result = run_chat(
                     ChatArgs {
                         messages: messages.clone(),
                         completion_args: completion.clone(),
-                        start_str: start_str.clone(),
-                        stop_str: stop_str.clone(),
+                        sections: sections.clone(),
                         hide_start_str: hide_start_str.clone(),
                         hide_stop_str: hide_stop_str.clone(),
                         n_outputs,
@@ -1441,6 +3425,7 @@ async fn execute_task(
                         extra_body: extra_body.clone(),
                         api_url: api_url.clone(),
                         api_key: api_key.clone(),
+                        tool_executor: tool_executor.clone(),
                     },
                     Some(&mut on_text),
                 )
@@ -1449,6 +3434,7 @@ async fn execute_task(
                     outputs,
                     visual_output,
                     raw,
+                    usage,
                 } = match result {
                     Ok(result) => result,
                     Err(err) => {
@@ -1463,10 +3449,10 @@ async fn execute_task(
                         return Err(err);
                     }
                 };
-                if outputs.len() < n_outputs as usize {
+                let found = outputs.iter().map(Vec::len).min().unwrap_or(0);
+                if found < n_outputs as usize {
                     io.write(format!(
-                        "\n(Expected {n_outputs} outputs, got {}. Retrying.)\n",
-                        outputs.len()
+                        "\n(Expected {n_outputs} outputs, got {found}. Retrying.)\n"
                     ))
                     .await;
                     sleep(Duration::from_secs(2)).await;
@@ -1476,6 +3462,7 @@ async fn execute_task(
                     outputs,
                     visual_output,
                     raw,
+                    usage,
                 };
             };
 
@@ -1484,19 +3471,56 @@ async fn execute_task(
                 guard.finish()?;
             }
 
-            let outputs_len = outputs.len();
+            let outputs_len = outputs.iter().map(Vec::len).sum::<usize>();
             let visual_len = visual_output.len();
-            if outputs_len == 1 {
-                with_inserts(state.clone(), |ins| {
-                    set_interpdata(ins, &output_name, Value::String(outputs[0].clone()))
-                })
-                .await;
-            } else {
-                with_inserts(state.clone(), |ins| {
-                    set_interpdata(ins, &output_name, Value::Array(outputs.into_iter().map(Value::String).collect()))
+            let outputs = outputs
+                .into_iter()
+                .map(|bucket| {
+                    bucket
+                        .into_iter()
+                        .map(|text| {
+                            if !json_output {
+                                return Ok(Value::String(text));
+                            }
+                            serde_json::from_str(&text).map(recursive_escape).map_err(|e| {
+                                anyhow!("chat output for '{output_name}' is not valid JSON ({e}): {text}")
+                            })
+                        })
+                        .collect::<Result<Vec<_>>>()
                 })
-                .await;
-            }
+                .collect::<Result<Vec<_>>>()?;
+            let bucket_value = |mut bucket: Vec<Value>| {
+                if bucket.len() == 1 {
+                    bucket.remove(0)
+                } else {
+                    Value::Array(bucket)
+                }
+            };
+            let result = match named_sections {
+                Some(named) => Value::Object(
+                    named
+                        .into_iter()
+                        .map(|(name, _)| name)
+                        .zip(outputs.into_iter().map(bucket_value))
+                        .collect(),
+                ),
+                None => bucket_value(outputs.into_iter().next().unwrap_or_default()),
+            };
+            let usage_value = match usage {
+                Some(u) => json!({
+                    "prompt_tokens": u.prompt_tokens,
+                    "completion_tokens": u.completion_tokens,
+                    "total_tokens": u.total_tokens,
+                }),
+                None => Value::Null,
+            };
+            with_inserts(state.clone(), |ins| {
+                set_interpdata(ins, &output_name, result);
+                if let Some(usage_name) = &usage_name {
+                    set_interpdata(ins, usage_name, usage_value.clone());
+                }
+            })
+            .await;
 
             logger.log(
                 "chat_done",
@@ -1504,6 +3528,7 @@ async fn execute_task(
                     "output_name": output_name,
                     "outputs": outputs_len,
                     "visual_len": visual_len,
+                    "usage": usage_value,
                     "messages": messages_for_log,
                     "assistant_raw": raw,
                 }),
@@ -1617,6 +3642,19 @@ fn eval_math_index(value: &Value, inserts: &Map<String, Value>, ctx: &ProgramLoa
     }
 }
 
+/// Like `eval_math_index`, but keeps fractional numbers; strings that are plain floats skip the integer-only math evaluator.
+fn eval_math_float(value: &Value, inserts: &Map<String, Value>, ctx: &ProgramLoadContext) -> Result<f64> {
+    if let Some(s) = value.as_str() {
+        let text = value_to_string(&interpolate_inserts(inserts, s, ctx)?);
+        match text.trim().parse::<f64>() {
+            Ok(parsed) => Ok(parsed),
+            Err(_) => Ok(eval_math(inserts, s, ctx)? as f64),
+        }
+    } else {
+        value.as_f64().ok_or_else(|| anyhow!("Value must be a number"))
+    }
+}
+
 fn slice_indices(from: i64, to: i64, len: usize) -> Result<(usize, usize)> {
     let len_i = len as i64;
     let start = if from > 0 { from - 1 } else { len_i + from };
@@ -1630,17 +3668,188 @@ fn slice_indices(from: i64, to: i64, len: usize) -> Result<(usize, usize)> {
     Ok((start as usize, end as usize))
 }
 
-fn wildcard_match(pattern: &str, s: &str) -> bool {
+/// Evaluates an `if` condition: a math expression, bool or number is truthy when non-zero.
+/// Also returns the evaluated value for logging.
+fn eval_condition(condition: &Value, inserts: &Map<String, Value>, ctx: &ProgramLoadContext) -> Result<(bool, Value)> {
+    match condition {
+        Value::String(s) => {
+            let value = eval_math(inserts, s, ctx)?;
+            Ok((value != 0, json!(value)))
+        }
+        Value::Bool(b) => Ok((*b, Value::Bool(*b))),
+        v => {
+            let n = v.as_f64().ok_or_else(|| anyhow!("if.condition must be a math expression"))?;
+            Ok((n != 0.0, json!(n)))
+        }
+    }
+}
+
+/// The tasks of the chosen `if` branch; a missing `else` yields no tasks.
+fn if_branch_tasks(task: &Task, take_then: bool) -> Result<Vec<Task>> {
+    let branch = if take_then { "then" } else { "else" };
+    match task.get(branch) {
+        None => Ok(Vec::new()),
+        Some(Value::Object(obj)) => Ok(vec![obj.clone()]),
+        Some(_) => as_task_array(task, branch),
+    }
+}
+
+/// Trims whitespace, or the given `chars`, from the sides selected by `mode`.
+fn trim_text<'a>(text: &'a str, mode: &str, chars: Option<&[char]>) -> Result<&'a str> {
+    let strip = |c: char| match chars {
+        Some(chars) => chars.contains(&c),
+        None => c.is_whitespace(),
+    };
+    match mode {
+        "both" => Ok(text.trim_matches(strip)),
+        "left" => Ok(text.trim_start_matches(strip)),
+        "right" => Ok(text.trim_end_matches(strip)),
+        _ => Err(anyhow!("string_trim.mode must be 'both', 'left' or 'right', got '{mode}'")),
+    }
+}
+
+fn convert_case(text: &str, upper: bool) -> String {
+    if upper {
+        text.to_uppercase()
+    } else {
+        text.to_lowercase()
+    }
+}
+
+/// Returns 1 when `item` equals an element of `list`, else 0, for use in math conditions.
+fn contains_flag(list: &[Value], item: &Value) -> i64 {
+    i64::from(list.contains(item))
+}
+
+/// Keeps the first occurrence of each value, in order.
+fn dedupe_values(list: Vec<Value>) -> Vec<Value> {
+    let mut unique: Vec<Value> = Vec::new();
+    for item in list {
+        if !unique.contains(&item) {
+            unique.push(item);
+        }
+    }
+    unique
+}
+
+fn sort_values(list: &mut [Value]) {
+    // Numbers compare numerically and come before every other value, which compare as text.
+    list.sort_by(|a, b| match (a.as_f64(), b.as_f64()) {
+        (Some(a), Some(b)) => a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal),
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => value_to_string(a).cmp(&value_to_string(b)),
+    });
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+fn coerce_value(item: &Value, to_type: &str) -> Option<Value> {
+    match (to_type, item) {
+        ("string", Value::String(_)) => Some(item.clone()),
+        ("string", Value::Array(_) | Value::Object(_)) => {
+            let text = serde_json::to_string(&recursive_unescape(item.clone())).ok()?;
+            Some(recursive_escape(Value::String(text)))
+        }
+        ("string", v) => Some(Value::String(value_to_string(v))),
+        ("number", Value::Number(_)) => Some(item.clone()),
+        ("number", Value::Bool(b)) => Some(Value::Number(i64::from(*b).into())),
+        ("number", Value::String(s)) => {
+            let s = s.trim();
+            match s.parse::<i64>() {
+                Ok(n) => Some(Value::Number(n.into())),
+                Err(_) => s.parse::<f64>().ok().and_then(serde_json::Number::from_f64).map(Value::Number),
+            }
+        }
+        ("integer", Value::Bool(b)) => Some(Value::Number(i64::from(*b).into())),
+        ("integer", Value::Number(_) | Value::String(_)) => {
+            let number = coerce_value(item, "number")?;
+            number
+                .as_i64()
+                .or_else(|| number.as_f64().filter(|f| f.fract() == 0.0).map(|f| f as i64))
+                .map(|n| Value::Number(n.into()))
+        }
+        ("boolean", Value::Bool(_)) => Some(item.clone()),
+        ("boolean", Value::Number(n)) => Some(Value::Bool(n.as_f64().unwrap_or(0.0) != 0.0)),
+        ("boolean", Value::String(s)) => match s.trim().to_lowercase().as_str() {
+            "true" | "1" => Some(Value::Bool(true)),
+            "false" | "0" => Some(Value::Bool(false)),
+            _ => None,
+        },
+        ("null", Value::Null) => Some(Value::Null),
+        ("null", Value::String(s)) if s.is_empty() || s == "null" => Some(Value::Null),
+        _ => None,
+    }
+}
+
+fn object_path_set(value: &mut Value, path: &str, item: Value) -> Result<()> {
+    let mut current = value;
+    for segment in path.split('.') {
+        if current.is_null() {
+            *current = Value::Object(Map::new());
+        }
+        current = match current {
+            Value::Object(obj) => obj.entry(segment.to_string()).or_insert(Value::Null),
+            Value::Array(arr) => {
+                let len = arr.len();
+                segment
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|idx| *idx > 0)
+                    .and_then(|idx| arr.get_mut(idx - 1))
+                    .ok_or_else(|| anyhow!("object_set index '{segment}' out of bounds for list of length {len}"))?
+            }
+            _ => return Err(anyhow!("object_set path '{path}' passes through a non-container value")),
+        };
+    }
+    *current = item;
+    Ok(())
+}
+
+fn deep_merge(target: &mut Map<String, Value>, source: Map<String, Value>) {
+    for (key, value) in source {
+        match (target.get_mut(&key), value) {
+            (Some(Value::Object(existing)), Value::Object(incoming)) => deep_merge(existing, incoming),
+            (_, value) => {
+                target.insert(key, value);
+            }
+        }
+    }
+}
+
+fn build_regex(task: &Task, pattern: &str) -> Result<regex::Regex> {
+    let pattern = pattern
+        .replace(&format!("{ESCAPE}{INSERT_START}"), &INSERT_START.to_string())
+        .replace(&format!("{ESCAPE}{INSERT_STOP}"), &INSERT_STOP.to_string());
+    regex::RegexBuilder::new(&pattern)
+        .multi_line(task.get("multi_line").and_then(Value::as_bool).unwrap_or(false))
+        .dot_matches_new_line(task.get("dot_all").and_then(Value::as_bool).unwrap_or(false))
+        .build()
+        .map_err(|e| anyhow!("Invalid regex '{pattern}': {e}"))
+}
+
+fn wildcard_match(pattern: &str, s: &str, case_insensitive: bool) -> bool {
     let mut regex = String::from("^");
     for ch in pattern.chars() {
         match ch {
             '*' => regex.push_str(".*"),
+            '?' => regex.push_str(r"[\s\S]"),
             _ => regex.push_str(&regex::escape(&ch.to_string())),
         }
     }
     regex.push('$');
     regex::RegexBuilder::new(&regex)
         .dot_matches_new_line(true)
+        .case_insensitive(case_insensitive)
         .build()
         .map(|re| re.is_match(s))
         .unwrap_or(false)
@@ -1652,6 +3861,7 @@ fn replace_map(
     inserts: &Map<String, Value>,
     ctx: &ProgramLoadContext,
     repeat_until_done: bool,
+    case_insensitive: bool,
 ) -> Result<Value> {
     let null_value = find_null_map_value(maps, inserts, ctx);
 
@@ -1661,6 +3871,7 @@ fn replace_map(
         inserts: &Map<String, Value>,
         ctx: &ProgramLoadContext,
         repeat_until_done: bool,
+        case_insensitive: bool,
     ) -> Result<String> {
         loop {
             let current = match interpolate_inserts(inserts, &text, ctx) {
@@ -1672,8 +3883,8 @@ fn replace_map(
                 let obj = map.as_object().ok_or_else(|| anyhow!("replace_map expects object"))?;
                 let (k, v) = obj.iter().next().ok_or_else(|| anyhow!("replace_map entry empty"))?;
                 let key = value_to_string(&interpolate_inserts(inserts, k, ctx)?);
-                if wildcard_match(&key, &current) {
-                    let captures = wildcard_captures(&key, &current);
+                if wildcard_match(&key, &current, case_insensitive) {
+                    let captures = wildcard_captures(&key, &current, case_insensitive);
                     let mut extra = inserts.clone();
                     for (i, cap) in captures.iter().enumerate() {
                         extra.insert((i + 1).to_string(), Value::String(cap.clone()));
@@ -1699,18 +3910,18 @@ fn replace_map(
             {
                 return Ok(null_value.unwrap());
             }
-            Ok(Value::String(replace_str(s, maps, inserts, ctx, repeat_until_done)?))
+            Ok(Value::String(replace_str(s, maps, inserts, ctx, repeat_until_done, case_insensitive)?))
         }
         Value::Array(arr) => Ok(Value::Array(
             arr.into_iter()
-                .map(|v| replace_map(v, maps, inserts, ctx, repeat_until_done))
+                .map(|v| replace_map(v, maps, inserts, ctx, repeat_until_done, case_insensitive))
                 .collect::<Result<Vec<_>>>()?,
         )),
         Value::Object(obj) => {
             let mut out = Map::new();
             for (k, v) in obj {
-                let new_k = replace_str(k, maps, inserts, ctx, repeat_until_done)?;
-                let new_v = replace_map(v, maps, inserts, ctx, repeat_until_done)?;
+                let new_k = replace_str(k, maps, inserts, ctx, repeat_until_done, case_insensitive)?;
+                let new_v = replace_map(v, maps, inserts, ctx, repeat_until_done, case_insensitive)?;
                 out.insert(new_k, new_v);
             }
             Ok(Value::Object(out))
@@ -1751,17 +3962,19 @@ fn find_null_map_value(maps: &[Value], inserts: &Map<String, Value>, ctx: &Progr
     None
 }
 
-fn wildcard_captures(pattern: &str, text: &str) -> Vec<String> {
+fn wildcard_captures(pattern: &str, text: &str, case_insensitive: bool) -> Vec<String> {
     let mut regex = String::from("^");
     for ch in pattern.chars() {
         match ch {
             '*' => regex.push_str("(.*)"),
+            '?' => regex.push_str(r"[\s\S]"),
             _ => regex.push_str(&regex::escape(&ch.to_string())),
         }
     }
     regex.push('$');
     let re = regex::RegexBuilder::new(&regex)
         .dot_matches_new_line(true)
+        .case_insensitive(case_insensitive)
         .build()
         .unwrap();
     if let Some(caps) = re.captures(text) {
@@ -1911,7 +4124,7 @@ async fn main_menu(
                 continue;
             }
             2 => {
-                let mut load_ctx = ProgramLoadContext::new(ctx.program_path.clone(), ctx.inserts_dir.clone())?;
+                let mut load_ctx = ProgramLoadContext::new(ctx.program_path.clone(), ctx.inserts_dirs.clone())?;
                 let new_program = crate::parser::load_program(&mut load_ctx)?;
                 crate::analyzer::analyze_program(&new_program, &load_ctx)?;
                 let mut st = state.lock().await;
@@ -2013,17 +4226,77 @@ fn interpolate_messages(
     for msg in arr {
         if let Some(obj) = msg.as_object() {
             let role = obj.get("role").and_then(Value::as_str).unwrap_or("user");
-            let content = obj.get("content").and_then(Value::as_str).unwrap_or("");
-            let content_val = interpolate_inserts(inserts, content, ctx)?;
+            let mut parts = match obj.get("content") {
+                Some(Value::Array(parts)) => parts
+                    .iter()
+                    .map(|part| interpolate_content_part(part, inserts, ctx))
+                    .collect::<Result<Vec<_>>>()?,
+                other => {
+                    let content = other.and_then(Value::as_str).unwrap_or("");
+                    let content_val = interpolate_inserts(inserts, content, ctx)?;
+                    vec![json!({"type": "text", "text": value_to_string(&content_val).trim()})]
+                }
+            };
+            let image_paths = match obj.get("content_image_path") {
+                None | Some(Value::Null) => Vec::new(),
+                Some(Value::Array(paths)) => paths.iter().map(value_to_string).collect(),
+                Some(v) => vec![value_to_string(v)],
+            };
+            for path in &image_paths {
+                let path = value_to_string(&interpolate_inserts(inserts, path, ctx)?);
+                parts.push(json!({"type": "image_url", "image_url": {"url": image_data_url(ctx, &path)?}}));
+            }
             let mut m = Map::new();
             m.insert("role".to_string(), Value::String(role.to_string()));
-            m.insert("content".to_string(), Value::String(value_to_string(&content_val).trim().to_string()));
+            let plain_text = obj.get("content").is_none_or(|c| !c.is_array()) && image_paths.is_empty();
+            if plain_text {
+                let text = parts[0].get("text").cloned().unwrap_or_default();
+                m.insert("content".to_string(), text);
+            } else {
+                parts.retain(|part| part.get("text").and_then(Value::as_str) != Some(""));
+                m.insert("content".to_string(), Value::Array(parts));
+            }
             out.push(m);
         }
     }
     Ok(out)
 }
 
+fn interpolate_content_part(part: &Value, inserts: &Map<String, Value>, ctx: &ProgramLoadContext) -> Result<Value> {
+    let mut part = part.clone();
+    let text = part
+        .get("text")
+        .and_then(Value::as_str)
+        .filter(|_| part.get("type").and_then(Value::as_str) == Some("text"))
+        .map(|s| s.to_string());
+    if let Some(text) = text {
+        let text = value_to_string(&interpolate_inserts(inserts, &text, ctx)?);
+        part["text"] = Value::String(text);
+    }
+    Ok(part)
+}
+
+/// Reads an image file and encodes it as a `data:` URL for `image_url` message parts.
+fn image_data_url(ctx: &ProgramLoadContext, path: &str) -> Result<String> {
+    let resolved = resolve_path(ctx, path);
+    let bytes = fs::read(&resolved)
+        .map_err(|e| anyhow!("content_image_path '{}' could not be read: {e}", resolved.display()))?;
+    let extension = resolved
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .to_lowercase();
+    let mime = match extension.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        _ => return Err(anyhow!("content_image_path '{}' is not a png, jpeg, gif or webp image", resolved.display())),
+    };
+    let encoded = encode_base64(&bytes);
+    Ok(format!("data:{mime};base64,{encoded}"))
+}
+
 #[derive(Clone)]
 enum Io {
     Ui(UiCommandHandle),
@@ -2055,10 +4328,30 @@ impl Io {
             }
         }
     }
-    async fn user_input(&self, prompt: String, default: String, allow_menu_toggle: bool) -> Result<String> {
+    async fn user_input(
+        &self,
+        prompt: String,
+        validate_pattern: Option<String>,
+        validate_error: Option<String>,
+        masked: bool,
+    ) -> Result<String> {
         match self {
-            Io::Ui(ui) => ui.user_input(prompt, default, allow_menu_toggle).await,
-            Io::Agent(agent) => agent.lock().await.user_input(prompt).await,
+            Io::Ui(ui) => {
+                ui.validated_input(prompt, String::new(), true, validate_pattern, validate_error, masked)
+                    .await
+            }
+            Io::Agent(agent) => {
+                let pattern = validate_pattern
+                    .map(|p| regex::Regex::new(&format!("^(?:{p})$")))
+                    .transpose()?;
+                let error = validate_error.unwrap_or_else(|| "Invalid input, please try again.".to_string());
+                let mut agent = agent.lock().await;
+                let mut input = agent.user_input(prompt.clone(), None, masked).await?;
+                while pattern.as_ref().is_some_and(|p| !p.is_match(&input)) {
+                    input = agent.user_input(prompt.clone(), Some(&error), masked).await?;
+                }
+                Ok(input)
+            }
         }
     }
     async fn select_index(&self, options: Vec<String>, description: Option<String>, allow_menu_toggle: bool) -> Result<usize> {
@@ -2067,6 +4360,22 @@ impl Io {
             Io::Agent(agent) => agent.lock().await.select_index(options, description).await,
         }
     }
+    async fn confirm(&self, question: String, default: Option<bool>) -> Result<bool> {
+        match self {
+            Io::Ui(ui) => ui.confirm(question, default).await,
+            Io::Agent(agent) => {
+                let options = vec!["Yes".to_string(), "No".to_string()];
+                let idx = agent.lock().await.select_index(options, Some(format!("{question} (y/n)"))).await?;
+                Ok(idx == 0)
+            }
+        }
+    }
+    async fn select_indices(&self, options: Vec<String>, description: Option<String>) -> Result<Vec<usize>> {
+        match self {
+            Io::Ui(ui) => ui.select_indices(options, description).await,
+            Io::Agent(agent) => agent.lock().await.select_indices(options, description).await,
+        }
+    }
     fn cancel_input(&self) {
         match self {
             Io::Ui(ui) => ui.cancel_input(),
@@ -2112,12 +4421,18 @@ impl AgentIo {
     fn set_output(&mut self, text: String) {
         self.output = text;
     }
-    async fn user_input(&mut self, prompt: String) -> Result<String> {
-        let payload = json!({
+    async fn user_input(&mut self, prompt: String, validation_error: Option<&str>, masked: bool) -> Result<String> {
+        let mut payload = json!({
             "type": "user_input",
             "output": self.output,
             "prompt": prompt,
         });
+        if masked {
+            payload["masked"] = Value::Bool(true);
+        }
+        if let Some(error) = validation_error {
+            payload["validation_error"] = Value::String(error.to_string());
+        }
         let _ = fs::remove_file(&self.input_path);
         fs::write(&self.output_path, serde_json::to_string_pretty(&payload)?)?;
         loop {
@@ -2150,11 +4465,7 @@ impl AgentIo {
                 sleep(Duration::from_millis(100)).await;
             }
         }
-        let keys = if options.len() <= 9 {
-            (1..=options.len()).map(|i| i.to_string()).collect::<Vec<_>>()
-        } else {
-            (0..options.len()).map(|i| ((b'a' + i as u8) as char).to_string()).collect()
-        };
+        let keys = agent_choice_keys(options.len());
         let choice_map: HashMap<String, usize> = keys.iter().enumerate().map(|(i, k)| (k.clone(), i)).collect();
         let payload = json!({
             "type": "user_choice",
@@ -2180,6 +4491,46 @@ impl AgentIo {
             sleep(Duration::from_millis(100)).await;
         }
     }
+    /// Multi-select variant of `select_index`: the agent answers with space-separated keys.
+    async fn select_indices(&mut self, options: Vec<String>, description: Option<String>) -> Result<Vec<usize>> {
+        let keys = agent_choice_keys(options.len());
+        let payload = json!({
+            "type": "user_choice",
+            "output": self.output,
+            "prompt": description,
+            "multi": true,
+            "choices": keys.iter().enumerate().map(|(i,k)| (k.clone(), options[i].clone())).collect::<HashMap<String,String>>(),
+        });
+        let _ = fs::remove_file(&self.input_path);
+        fs::write(&self.output_path, serde_json::to_string_pretty(&payload)?)?;
+        loop {
+            if self.input_path.exists() {
+                let data = fs::read_to_string(&self.input_path)?;
+                let _ = fs::remove_file(&self.input_path);
+                let mut selected = Vec::new();
+                for key in data.split_whitespace() {
+                    let idx = keys
+                        .iter()
+                        .position(|k| k == key)
+                        .ok_or_else(|| anyhow!("Invalid agent choice '{key}'"))?;
+                    if !selected.contains(&idx) {
+                        selected.push(idx);
+                    }
+                }
+                selected.sort_unstable();
+                return Ok(selected);
+            }
+            sleep(Duration::from_millis(100)).await;
+        }
+    }
+}
+
+fn agent_choice_keys(count: usize) -> Vec<String> {
+    if count <= 9 {
+        (1..=count).map(|i| i.to_string()).collect()
+    } else {
+        (0..count).map(|i| ((b'a' + i as u8) as char).to_string()).collect()
+    }
 }
 
 struct TtsWriter {
@@ -2350,3 +4701,161 @@ fn last_sentence_end(text: &str) -> Option<usize> {
     }
     last
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_ctx() -> ProgramLoadContext {
+        ProgramLoadContext::new(std::path::PathBuf::from("test.json5"), Vec::new()).unwrap()
+    }
+
+    #[test]
+    fn wildcard_question_mark_matches_one_char() {
+        assert!(wildcard_match("?at", "cat", false));
+        assert!(wildcard_match("c?t", "cut", false));
+        assert!(wildcard_match("ca?", "cab", false));
+        assert!(!wildcard_match("?at", "at", false));
+        assert!(!wildcard_match("ca?", "cabs", false));
+    }
+
+    #[test]
+    fn wildcard_adjacent_question_marks() {
+        assert!(wildcard_match("a??d", "abcd", false));
+        assert!(!wildcard_match("a??d", "abd", false));
+        assert!(!wildcard_match("a??d", "abcde", false));
+    }
+
+    #[test]
+    fn wildcard_question_mark_with_star() {
+        assert!(wildcard_match("?*", "x", false));
+        assert!(!wildcard_match("?*", "", false));
+        assert!(wildcard_match("*.?s", "main.rs", false));
+        assert!(wildcard_match("h?llo *", "hello world", false));
+        assert!(!wildcard_match("*.?s", "main.rst", false));
+    }
+
+    #[test]
+    fn wildcard_case_insensitive() {
+        assert!(wildcard_match("HELLO", "hello", true));
+        assert!(!wildcard_match("HELLO", "hello", false));
+        assert!(!wildcard_match("HELLO", "help", true));
+    }
+
+    #[test]
+    fn wildcard_case_insensitive_with_star() {
+        assert!(wildcard_match("yes*", "YES PLEASE", true));
+        assert!(wildcard_match("*Ok", "that is ok", true));
+        assert!(!wildcard_match("yes*", "no, YES", true));
+    }
+
+    #[test]
+    fn replace_map_fills_wildcard_captures() {
+        let maps = vec![json!({"hello *": "you said: {1}"})];
+        let result = replace_map(json!("hello world"), &maps, &Map::new(), &test_ctx(), false, false).unwrap();
+        assert_eq!(result, json!("you said: world"));
+    }
+
+    #[test]
+    fn dedupe_keeps_first_occurrences_in_order() {
+        let list = vec![json!(1), json!("a"), json!(1), Value::Null, json!("a")];
+        assert_eq!(dedupe_values(list), vec![json!(1), json!("a"), Value::Null]);
+        assert!(dedupe_values(Vec::new()).is_empty());
+    }
+
+    #[test]
+    fn sort_strings_ascending() {
+        let mut list = vec![json!("pear"), json!("apple"), json!("fig")];
+        sort_values(&mut list);
+        assert_eq!(list, vec![json!("apple"), json!("fig"), json!("pear")]);
+    }
+
+    #[test]
+    fn sort_numbers_descending() {
+        let mut list = vec![json!(2), json!(10), json!(-1.5), json!(3)];
+        sort_values(&mut list);
+        list.reverse();
+        assert_eq!(list, vec![json!(10), json!(3), json!(2), json!(-1.5)]);
+    }
+
+    #[test]
+    fn sort_empty_and_single() {
+        let mut empty: Vec<Value> = Vec::new();
+        sort_values(&mut empty);
+        assert!(empty.is_empty());
+        let mut single = vec![json!("only")];
+        sort_values(&mut single);
+        assert_eq!(single, vec![json!("only")]);
+    }
+
+    #[test]
+    fn contains_every_value_type() {
+        let list = vec![
+            Value::Null,
+            json!(true),
+            json!(3),
+            json!(2.5),
+            json!("text"),
+            json!([1, 2]),
+            json!({"k": "v"}),
+        ];
+        for item in &list {
+            assert_eq!(contains_flag(&list, item), 1, "{item}");
+        }
+        for item in [json!(false), json!(4), json!(2.25), json!("other"), json!([2, 1]), json!({"k": "w"})] {
+            assert_eq!(contains_flag(&list, &item), 0, "{item}");
+        }
+        assert_eq!(contains_flag(&[], &Value::Null), 0);
+    }
+
+    #[test]
+    fn trim_ascii_and_cjk() {
+        assert_eq!(trim_text("  hello \n", "both", None).unwrap(), "hello");
+        assert_eq!(trim_text("  hello  ", "left", None).unwrap(), "hello  ");
+        assert_eq!(trim_text("  hello  ", "right", None).unwrap(), "  hello");
+        assert_eq!(trim_text("\u{3000}你好世界\u{3000}", "both", None).unwrap(), "你好世界");
+        assert_eq!(trim_text("「こんにちは」", "both", Some(&['「', '」'])).unwrap(), "こんにちは");
+        assert!(trim_text("x", "middle", None).is_err());
+    }
+
+    #[test]
+    fn convert_case_ascii_mixed_and_cjk() {
+        assert_eq!(convert_case("hello", true), "HELLO");
+        assert_eq!(convert_case("MiXeD CaSe", false), "mixed case");
+        assert_eq!(convert_case("MiXeD CaSe", true), "MIXED CASE");
+        assert_eq!(convert_case("Straße", true), "STRASSE");
+        assert_eq!(convert_case("漢字かなABC", false), "漢字かなabc");
+        assert_eq!(convert_case("漢字かなabc", true), "漢字かなABC");
+    }
+
+    #[test]
+    fn if_condition_truthy_and_falsy() {
+        let ctx = test_ctx();
+        let inserts = json!({"n": 3}).as_object().cloned().unwrap();
+        assert!(eval_condition(&json!("{n} - 2"), &inserts, &ctx).unwrap().0);
+        assert!(!eval_condition(&json!("{n} - 3"), &inserts, &ctx).unwrap().0);
+        assert!(eval_condition(&json!(true), &inserts, &ctx).unwrap().0);
+        assert!(!eval_condition(&json!(0), &inserts, &ctx).unwrap().0);
+        assert!(eval_condition(&json!([1]), &inserts, &ctx).is_err());
+    }
+
+    #[test]
+    fn if_branch_selection() {
+        let task = json!({
+            "cmd": "if",
+            "condition": "1",
+            "then": {"cmd": "print", "text": "yes"},
+            "else": [{"cmd": "print", "text": "no"}, {"cmd": "clear"}],
+        });
+        let task = task.as_object().cloned().unwrap();
+        assert_eq!(if_branch_tasks(&task, true).unwrap().len(), 1);
+        assert_eq!(if_branch_tasks(&task, false).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn if_without_else_runs_nothing() {
+        let task = json!({"cmd": "if", "condition": "0", "then": {"cmd": "print", "text": "yes"}});
+        let task = task.as_object().cloned().unwrap();
+        assert!(if_branch_tasks(&task, false).unwrap().is_empty());
+    }
+}
@@ -7,9 +7,11 @@ use ratatui::{
     backend::CrosstermBackend,
     layout::{Constraint, Direction, Layout},
     style::{Color, Style},
+    text::{Line, Text},
     widgets::{Block, Borders, Paragraph, Wrap},
     Terminal,
 };
+use regex::Regex;
 use std::io::{self, Stdout, Write};
 use std::path::PathBuf;
 use std::sync::mpsc::{self, Receiver, Sender};
@@ -33,13 +35,22 @@ pub enum UiCommand {
         prompt: String,
         default: String,
         allow_menu_toggle: bool,
+        validate_pattern: Option<String>,
+        validate_error: Option<String>,
+        masked: bool,
         respond_to: oneshot::Sender<String>,
     },
     BeginChoice {
         options: Vec<String>,
         description: Option<String>,
         allow_menu_toggle: bool,
-        respond_to: oneshot::Sender<usize>,
+        multi_select: bool,
+        respond_to: oneshot::Sender<Vec<usize>>,
+    },
+    BeginConfirm {
+        question: String,
+        default: Option<bool>,
+        respond_to: oneshot::Sender<bool>,
     },
     CancelInput,
     Shutdown,
@@ -50,10 +61,13 @@ pub struct UiCommandHandle {
     cmd_tx: Sender<UiCommand>,
 }
 
-pub fn start_ui(history_path: Option<PathBuf>) -> (UiCommandHandle, tokio::sync::mpsc::UnboundedReceiver<UiEvent>, JoinHandle<()>) {
+pub fn start_ui(
+    history_path: Option<PathBuf>,
+    output_buffer_lines: Option<usize>,
+) -> (UiCommandHandle, tokio::sync::mpsc::UnboundedReceiver<UiEvent>, JoinHandle<()>) {
     let (cmd_tx, cmd_rx) = mpsc::channel();
     let (event_tx, event_rx) = tokio::sync::mpsc::unbounded_channel();
-    let handle = spawn_ui_thread(cmd_rx, event_tx, history_path);
+    let handle = spawn_ui_thread(cmd_rx, event_tx, history_path, output_buffer_lines);
     (UiCommandHandle { cmd_tx }, event_rx, handle)
 }
 
@@ -72,11 +86,28 @@ impl UiCommandHandle {
     }
 
     pub async fn user_input(&self, prompt: String, default: String, allow_menu_toggle: bool) -> Result<String> {
+        self.validated_input(prompt, default, allow_menu_toggle, None, None, false).await
+    }
+
+    /// Like `user_input`, but keeps prompting until the input fully matches `validate_pattern`.
+    /// A `masked` input is drawn as bullets and never written to the history.
+    pub async fn validated_input(
+        &self,
+        prompt: String,
+        default: String,
+        allow_menu_toggle: bool,
+        validate_pattern: Option<String>,
+        validate_error: Option<String>,
+        masked: bool,
+    ) -> Result<String> {
         let (tx, rx) = oneshot::channel();
         let _ = self.cmd_tx.send(UiCommand::BeginInput {
             prompt,
             default,
             allow_menu_toggle,
+            validate_pattern,
+            validate_error,
+            masked,
             respond_to: tx,
         });
         match rx.await {
@@ -91,11 +122,42 @@ impl UiCommandHandle {
         description: Option<String>,
         allow_menu_toggle: bool,
     ) -> Result<usize> {
+        let selected = self.begin_choice(options, description, allow_menu_toggle, false).await?;
+        selected.first().copied().ok_or_else(|| anyhow::anyhow!("cancelled"))
+    }
+
+    /// Lets the user toggle any number of options and returns the selected indices in list order.
+    pub async fn select_indices(&self, options: Vec<String>, description: Option<String>) -> Result<Vec<usize>> {
+        self.begin_choice(options, description, true, true).await
+    }
+
+    async fn begin_choice(
+        &self,
+        options: Vec<String>,
+        description: Option<String>,
+        allow_menu_toggle: bool,
+        multi_select: bool,
+    ) -> Result<Vec<usize>> {
         let (tx, rx) = oneshot::channel();
         let _ = self.cmd_tx.send(UiCommand::BeginChoice {
             options,
             description,
             allow_menu_toggle,
+            multi_select,
+            respond_to: tx,
+        });
+        match rx.await {
+            Ok(value) => Ok(value),
+            Err(_) => Err(anyhow::anyhow!("cancelled")),
+        }
+    }
+
+    /// Asks a yes/no question; Enter picks `default` when one is given.
+    pub async fn confirm(&self, question: String, default: Option<bool>) -> Result<bool> {
+        let (tx, rx) = oneshot::channel();
+        let _ = self.cmd_tx.send(UiCommand::BeginConfirm {
+            question,
+            default,
             respond_to: tx,
         });
         match rx.await {
@@ -136,20 +198,38 @@ enum Mode {
         description: Option<String>,
         options: Vec<String>,
         keys: Vec<String>,
+        highlighted: usize,
+        multi_select: bool,
+        selected: Vec<bool>,
         allow_menu_toggle: bool,
-        respond_to: Option<oneshot::Sender<usize>>,
+        respond_to: Option<oneshot::Sender<Vec<usize>>>,
+    },
+    Confirm {
+        question: String,
+        default: Option<bool>,
+        respond_to: Option<oneshot::Sender<bool>>,
     },
 }
 
+/// Pattern the current input must match before Enter submits it.
+struct InputValidation {
+    pattern: Regex,
+    error: String,
+    outline: String,
+}
+
 struct UiState {
     output: String,
     info: String,
     mode: Mode,
+    validation: Option<InputValidation>,
+    masked: bool,
     history_path: Option<PathBuf>,
     history: Vec<String>,
     history_cursor: Option<usize>,
     history_stash: Option<String>,
     output_scroll: usize,
+    output_buffer_lines: Option<usize>,
     auto_scroll: bool,
     last_layout: Option<LayoutInfo>,
     output_line_count: usize,
@@ -168,6 +248,7 @@ fn spawn_ui_thread(
     cmd_rx: Receiver<UiCommand>,
     event_tx: UnboundedSender<UiEvent>,
     history_path: Option<PathBuf>,
+    output_buffer_lines: Option<usize>,
 ) -> JoinHandle<()> {
     thread::spawn(move || {
         let mut terminal = setup_terminal().ok();
@@ -175,11 +256,14 @@ fn spawn_ui_thread(
             output: String::new(),
             info: String::new(),
             mode: Mode::Idle,
+            validation: None,
+            masked: false,
             history_path,
             history: Vec::new(),
             history_cursor: None,
             history_stash: None,
             output_scroll: 0,
+            output_buffer_lines,
             auto_scroll: true,
             last_layout: None,
             output_line_count: 0,
@@ -246,6 +330,7 @@ fn handle_command(cmd: UiCommand, state: &mut UiState) -> bool {
     match cmd {
         UiCommand::Write(text) => {
             state.output.push_str(&text);
+            trim_output_buffer(state);
             state.output_dirty = true;
             if state.auto_scroll {
                 if let Some(layout) = state.last_layout {
@@ -275,10 +360,21 @@ fn handle_command(cmd: UiCommand, state: &mut UiState) -> bool {
             prompt,
             default,
             allow_menu_toggle,
+            validate_pattern,
+            validate_error,
+            masked,
             respond_to,
         } => {
+            state.masked = masked;
             let (outline, inline) = split_prompt(&prompt);
             let cursor = default.len();
+            state.validation = validate_pattern
+                .and_then(|pattern| Regex::new(&format!("^(?:{pattern})$")).ok())
+                .map(|pattern| InputValidation {
+                    pattern,
+                    error: validate_error.unwrap_or_else(|| "Invalid input, please try again.".to_string()),
+                    outline: outline.clone(),
+                });
             state.info = outline;
             state.mode = Mode::Input {
                 prompt_inline: inline,
@@ -295,21 +391,38 @@ fn handle_command(cmd: UiCommand, state: &mut UiState) -> bool {
             options,
             description,
             allow_menu_toggle,
+            multi_select,
             respond_to,
         } => {
             let keys = build_choice_keys(options.len());
+            let selected = vec![false; options.len()];
             state.mode = Mode::Choice {
                 description,
                 options,
                 keys,
+                highlighted: 0,
+                multi_select,
+                selected,
                 allow_menu_toggle,
                 respond_to: Some(respond_to),
             };
             true
         }
+        UiCommand::BeginConfirm {
+            question,
+            default,
+            respond_to,
+        } => {
+            state.mode = Mode::Confirm {
+                question,
+                default,
+                respond_to: Some(respond_to),
+            };
+            true
+        }
         UiCommand::CancelInput => {
             match &mut state.mode {
-                Mode::Input { .. } | Mode::Search { .. } | Mode::Choice { .. } => {
+                Mode::Input { .. } | Mode::Search { .. } | Mode::Choice { .. } | Mode::Confirm { .. } => {
                     state.mode = Mode::Idle;
                     true
                 }
@@ -320,6 +433,29 @@ fn handle_command(cmd: UiCommand, state: &mut UiState) -> bool {
     }
 }
 
+/// Drops whole lines from the top of the output so at most `output_buffer_lines` remain.
+fn trim_output_buffer(state: &mut UiState) {
+    let Some(max_lines) = state.output_buffer_lines else { return };
+    let line_count = state.output.matches('\n').count() + 1;
+    if line_count <= max_lines {
+        return;
+    }
+    let excess = line_count - max_lines;
+    let cut = state
+        .output
+        .match_indices('\n')
+        .nth(excess - 1)
+        .map(|(i, _)| i + 1)
+        .unwrap_or(0);
+    let removed: String = state.output.drain(..cut).collect();
+    // The scroll offset counts wrapped display rows, so subtract the rows the removed lines took up.
+    let removed_rows = removed
+        .split_terminator('\n')
+        .map(|line| paragraph_line_count(line, state.output_line_width).max(1))
+        .sum::<usize>();
+    state.output_scroll = state.output_scroll.saturating_sub(removed_rows);
+}
+
 fn handle_key(key: KeyEvent, state: &mut UiState, event_tx: &UnboundedSender<UiEvent>) -> (bool, bool) {
     if key.code == KeyCode::Esc {
         match &state.mode {
@@ -351,11 +487,22 @@ fn handle_key(key: KeyEvent, state: &mut UiState, event_tx: &UnboundedSender<UiE
         } => match key.code {
             KeyCode::Enter => {
                 let text = buffer.clone();
-                if let Some(path) = &state.history_path {
-                    let _ = append_history(path, &text);
+                if let Some(validation) = state.validation.as_ref().filter(|v| !v.pattern.is_match(&text)) {
+                    state.info = if validation.outline.is_empty() {
+                        validation.error.clone()
+                    } else {
+                        format!("{}\n{}", validation.outline, validation.error)
+                    };
+                    return (false, true);
                 }
-                if !text.is_empty() {
-                    state.history.push(text.clone());
+                state.validation = None;
+                if !state.masked {
+                    if let Some(path) = &state.history_path {
+                        let _ = append_history(path, &text);
+                    }
+                    if !text.is_empty() {
+                        state.history.push(text.clone());
+                    }
                 }
                 if let Some(tx) = respond_to.take() {
                     let _ = tx.send(text);
@@ -378,7 +525,7 @@ fn handle_key(key: KeyEvent, state: &mut UiState, event_tx: &UnboundedSender<UiE
                 state.history_cursor = None;
                 changed = true;
             }
-            KeyCode::Char('r') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            KeyCode::Char('r') if key.modifiers.contains(KeyModifiers::CONTROL) && !state.masked => {
                 let original = buffer.clone();
                 let (prompt_inline, allow_menu_toggle, respond_to) = match std::mem::replace(&mut state.mode, Mode::Idle) {
                     Mode::Input { prompt_inline, allow_menu_toggle, respond_to, .. } => {
@@ -637,6 +784,9 @@ fn handle_key(key: KeyEvent, state: &mut UiState, event_tx: &UnboundedSender<UiE
         Mode::Choice {
             options,
             keys,
+            highlighted,
+            multi_select,
+            selected,
             respond_to,
             ..
         } => {
@@ -650,30 +800,73 @@ fn handle_key(key: KeyEvent, state: &mut UiState, event_tx: &UnboundedSender<UiE
                 return (false, changed);
             }
             match key.code {
+                KeyCode::Char(' ') if *multi_select => {
+                    selected[*highlighted] = !selected[*highlighted];
+                    changed = true;
+                }
                 KeyCode::Char(c) => {
                     let key_str = c.to_string();
-                    if let Some(idx) = keys.iter().position(|k| k == &key_str) {
-                        if let Some(tx) = respond_to.take() {
-                            let _ = tx.send(idx);
-                        }
-                        state.mode = Mode::Idle;
-                        changed = true;
-                    } else {
-                        if let Some(idx) = options.iter().position(|o| o == &key_str) {
+                    let idx = keys
+                        .iter()
+                        .position(|k| k == &key_str)
+                        .or_else(|| options.iter().position(|o| o == &key_str));
+                    if let Some(idx) = idx {
+                        if *multi_select {
+                            selected[idx] = !selected[idx];
+                            *highlighted = idx;
+                        } else {
                             if let Some(tx) = respond_to.take() {
-                                let _ = tx.send(idx);
+                                let _ = tx.send(vec![idx]);
                             }
                             state.mode = Mode::Idle;
-                            changed = true;
                         }
+                        changed = true;
                     }
                 }
+                KeyCode::Up => {
+                    *highlighted = highlighted.checked_sub(1).unwrap_or(options.len() - 1);
+                    changed = true;
+                }
+                KeyCode::Down => {
+                    *highlighted = (*highlighted + 1) % options.len();
+                    changed = true;
+                }
+                KeyCode::Enter => {
+                    let chosen = if *multi_select {
+                        (0..options.len()).filter(|&i| selected[i]).collect()
+                    } else {
+                        vec![*highlighted]
+                    };
+                    if let Some(tx) = respond_to.take() {
+                        let _ = tx.send(chosen);
+                    }
+                    state.mode = Mode::Idle;
+                    changed = true;
+                }
                 KeyCode::PageUp | KeyCode::PageDown | KeyCode::Home | KeyCode::End => {
                     changed = scroll_output_key(key.code, state);
                 }
                 _ => {}
             }
         }
+        Mode::Confirm { default, respond_to, .. } => {
+            let answer = match key.code {
+                KeyCode::Char('y') | KeyCode::Char('Y') => Some(true),
+                KeyCode::Char('n') | KeyCode::Char('N') => Some(false),
+                KeyCode::Enter => *default,
+                KeyCode::PageUp | KeyCode::PageDown | KeyCode::Home | KeyCode::End => {
+                    return (false, scroll_output_key(key.code, state));
+                }
+                _ => None,
+            };
+            if let Some(answer) = answer {
+                if let Some(tx) = respond_to.take() {
+                    let _ = tx.send(answer);
+                }
+                state.mode = Mode::Idle;
+                changed = true;
+            }
+        }
         Mode::Idle => {
             match key.code {
                 KeyCode::PageUp | KeyCode::PageDown | KeyCode::Home | KeyCode::End => {
@@ -808,20 +1001,35 @@ fn find_history_match(history: &[String], query: &str, start_from: Option<usize>
 fn draw(terminal: &mut Terminal<CrosstermBackend<Stdout>>, state: &mut UiState) -> io::Result<()> {
     terminal.draw(|f| {
         let size = f.size();
+        let mut highlight_line = None;
         let info_text = match &state.mode {
-            Mode::Choice { description, options, keys, .. } => {
+            Mode::Choice { description, options, keys, highlighted, multi_select, selected, .. } => {
                 let mut lines = Vec::new();
                 if let Some(desc) = description {
-                    lines.push(desc.clone());
+                    lines.extend(desc.split('\n').map(str::to_string));
                 }
                 for (i, opt) in options.iter().enumerate() {
                     if let Some(k) = keys.get(i) {
-                        lines.push(format!("({}) {}", k, opt));
+                        let check = match (*multi_select, selected[i]) {
+                            (false, _) => "",
+                            (true, true) => "[x] ",
+                            (true, false) => "[ ] ",
+                        };
+                        if i == *highlighted {
+                            highlight_line = Some(lines.len());
+                            lines.push(format!("> {check}({}) {}", k, opt));
+                        } else {
+                            lines.push(format!("  {check}({}) {}", k, opt));
+                        }
                     }
                 }
+                if *multi_select {
+                    lines.push("Press a key or Space to toggle, Enter to submit.".to_string());
+                }
                 lines.join("\n")
             }
             Mode::Input { .. } => state.info.clone(),
+            Mode::Confirm { question, .. } => format!("{question} (y/n)"),
             Mode::Search { query, .. } => format!("reverse-i-search: {query}"),
             _ => String::new(),
         };
@@ -830,10 +1038,18 @@ fn draw(terminal: &mut Terminal<CrosstermBackend<Stdout>>, state: &mut UiState)
             Mode::Input { prompt_inline, buffer, cursor, .. } => {
                 let c = (*cursor).min(buffer.len());
                 let cursor_slice = &buffer[..c];
-                (
-                    format!("{prompt_inline}{buffer}"),
-                    Some(format!("{prompt_inline}{cursor_slice}")),
-                )
+                if state.masked {
+                    let bullets = |text: &str| "\u{2022}".repeat(text.chars().count());
+                    (
+                        format!("{prompt_inline}{}", bullets(buffer)),
+                        Some(format!("{prompt_inline}{}", bullets(cursor_slice))),
+                    )
+                } else {
+                    (
+                        format!("{prompt_inline}{buffer}"),
+                        Some(format!("{prompt_inline}{cursor_slice}")),
+                    )
+                }
             }
             Mode::Search { prompt_inline, buffer, .. } => (format!("{prompt_inline}{buffer}"), None),
             _ => (String::new(), None),
@@ -845,7 +1061,7 @@ fn draw(terminal: &mut Terminal<CrosstermBackend<Stdout>>, state: &mut UiState)
         let info_pref = line_count_no_wrap(&info_text).min(height);
 
         let (mut output_height, info_height) = match &state.mode {
-            Mode::Choice { .. } | Mode::Input { .. } | Mode::Search { .. } => {
+            Mode::Choice { .. } | Mode::Input { .. } | Mode::Search { .. } | Mode::Confirm { .. } => {
                 let available = height.saturating_sub(prompt_height);
                 let info_height = info_pref.min(available);
                 let output_height = available.saturating_sub(info_height);
@@ -895,7 +1111,18 @@ fn draw(terminal: &mut Terminal<CrosstermBackend<Stdout>>, state: &mut UiState)
             .block(Block::default().borders(Borders::NONE));
         f.render_widget(output, chunks[0]);
 
-        let info = Paragraph::new(info_text.clone())
+        let info_lines: Vec<Line> = info_text
+            .split('\n')
+            .enumerate()
+            .map(|(i, line)| {
+                if Some(i) == highlight_line {
+                    Line::styled(line, Style::default().fg(Color::Green))
+                } else {
+                    Line::raw(line)
+                }
+            })
+            .collect();
+        let info = Paragraph::new(Text::from(info_lines))
             .style(Style::default().fg(Color::Yellow))
             .block(Block::default().borders(Borders::NONE));
         f.render_widget(info, chunks[1]);